@@ -0,0 +1,130 @@
+use std::path::PathBuf;
+
+use europa_terrain::export::mesh::{MeshFormat, export_mesh};
+use europa_terrain::{
+    EUROPA_RADIUS, TerrainParams, TerrainRecipe, build_europa_mesh, build_europa_mesh_adaptive,
+    europa_height,
+};
+
+const USAGE: &str = "usage: europa_app export-mesh <out.glb|out.obj|out.stl> \
+[--size <m>] [--res <quads>] [--seed <n>] [--recipe <file.height.ron>] [--max-error <m>] [--curved]";
+
+/// `export-mesh`: bake the terrain mesh to disk without opening a window
+pub fn export_mesh_cmd(args: &[String]) -> Result<(), String> {
+    let mut out: Option<PathBuf> = None;
+    let mut recipe: Option<PathBuf> = None;
+    let mut max_error: Option<f32> = None;
+    let mut params = TerrainParams::europa_demo();
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = |name: &str| {
+            it.next()
+                .cloned()
+                .ok_or_else(|| format!("{name} needs a value\n{USAGE}"))
+        };
+        match arg.as_str() {
+            "--size" => params.size = parse(&value("--size")?)?,
+            "--res" => params.res = parse(&value("--res")?)?,
+            "--seed" => params.seed = parse(&value("--seed")?)?,
+            "--recipe" => recipe = Some(value("--recipe")?.into()),
+            "--max-error" => max_error = Some(parse(&value("--max-error")?)?),
+            "--curved" => params.curvature = Some(EUROPA_RADIUS),
+            "-h" | "--help" => return Err(USAGE.into()),
+            other if out.is_none() && !other.starts_with('-') => out = Some(other.into()),
+            other => return Err(format!("unexpected argument {other}\n{USAGE}")),
+        }
+    }
+
+    let out = out.ok_or(USAGE)?;
+    let format = MeshFormat::from_path(&out)
+        .ok_or_else(|| format!("{} needs a .glb, .obj or .stl extension", out.display()))?;
+
+    let height = match recipe {
+        Some(path) => TerrainRecipe::from_file(&path)
+            .map_err(|e| format!("{}: {e}", path.display()))?
+            .height
+            .build(&params)
+            .map_err(|e| format!("{}: {e}", path.display()))?,
+        None => europa_height(&params),
+    };
+    let height = params.surface(height);
+
+    // with --max-error the grid is thinned to an RTIN mesh within that many meters
+    let Some(max_error) = max_error else {
+        let mesh = build_europa_mesh(params, height.as_ref());
+        export_mesh(&mesh, &out, format).map_err(|e| format!("{}: {e}", out.display()))?;
+        println!(
+            "wrote {} ({} m, {}x{} quads)",
+            out.display(),
+            params.size,
+            params.res,
+            params.res
+        );
+        return Ok(());
+    };
+
+    let (mesh, stats) = build_europa_mesh_adaptive(params, height.as_ref(), max_error);
+    export_mesh(&mesh, &out, format).map_err(|e| format!("{}: {e}", out.display()))?;
+    println!(
+        "wrote {} ({} m, {} triangles of {} in the full grid, {} vertices, error <= {:.3} m)",
+        out.display(),
+        params.size,
+        stats.triangles,
+        stats.full_triangles,
+        stats.vertices,
+        stats.max_error
+    );
+    Ok(())
+}
+
+fn parse<T: std::str::FromStr>(s: &str) -> Result<T, String> {
+    s.parse().map_err(|_| format!("bad number {s}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(args: &[&str]) -> Result<(), String> {
+        export_mesh_cmd(&args.iter().map(|a| a.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn flag_without_value_is_an_error() {
+        let err = run(&["out.obj", "--res"]).unwrap_err();
+        assert!(err.starts_with("--res needs a value"), "{err}");
+    }
+
+    #[test]
+    fn unknown_flags_are_rejected() {
+        let err = run(&["out.obj", "--bogus"]).unwrap_err();
+        assert!(err.starts_with("unexpected argument --bogus"), "{err}");
+        let err = run(&["a.obj", "b.obj"]).unwrap_err();
+        assert!(err.starts_with("unexpected argument b.obj"), "{err}");
+    }
+
+    #[test]
+    fn bad_numbers_and_outputs_are_rejected() {
+        assert_eq!(
+            run(&["out.obj", "--res", "lots"]).unwrap_err(),
+            "bad number lots"
+        );
+        assert_eq!(run(&["--res", "8"]).unwrap_err(), USAGE);
+        let err = run(&["out.ply", "--res", "8"]).unwrap_err();
+        assert!(
+            err.contains("needs a .glb, .obj or .stl extension"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn writes_the_requested_grid() {
+        let path = std::env::temp_dir().join("europa_cli_export.stl");
+        run(&[path.to_str().unwrap(), "--size", "40", "--res", "4"]).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        let triangles = u32::from_le_bytes(bytes[80..84].try_into().unwrap());
+        assert_eq!(triangles, 4 * 4 * 2);
+    }
+}
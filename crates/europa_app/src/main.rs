@@ -2,7 +2,18 @@ use bevy::prelude::*;
 use bevy::window::{PresentMode, WindowPlugin};
 use europa_scene::ScenePlugin;
 
+mod cli;
+
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|a| a == "export-mesh") {
+        if let Err(e) = cli::export_mesh_cmd(&args[1..]) {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     App::new()
         .add_plugins(
             DefaultPlugins
@@ -19,6 +30,8 @@ fn main() {
                     ..default()
                 }),
         )
-        .add_plugins(ScenePlugin)
+        .add_plugins(ScenePlugin {
+            globe: args.iter().any(|a| a == "--globe"),
+        })
         .run();
 }
@@ -1,4 +1,61 @@
 pub fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
     let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
     t * t * (3.0 - 2.0 * t)
-}
\ No newline at end of file
+}
+
+/// integer avalanche hash (lowbias32), good enough for placement, not for crypto
+pub fn hash_u32(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x
+}
+
+/// hash of a seeded integer lattice cell
+pub fn hash_cell(seed: u32, x: i32, z: i32) -> u32 {
+    hash_u32(seed ^ hash_u32((x as u32) ^ hash_u32(z as u32).rotate_left(13)))
+}
+
+/// tiny deterministic stream of random numbers seeded from a hash
+#[derive(Clone, Copy, Debug)]
+pub struct HashRng(pub u32);
+
+impl HashRng {
+    pub fn next_u32(&mut self) -> u32 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9);
+        hash_u32(self.0)
+    }
+
+    /// uniform in `[0, 1)`
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    pub fn range(&mut self, lo: f32, hi: f32) -> f32 {
+        lo + (hi - lo) * self.next_f32()
+    }
+
+    /// poisson draw, knuth's method for small means and a normal
+    /// approximation above 30 so dense configs can't loop forever
+    pub fn poisson(&mut self, lambda: f32) -> u32 {
+        if lambda <= 0.0 {
+            return 0;
+        }
+        if lambda > 30.0 {
+            let u = self.next_f32().max(1e-7);
+            let v = self.next_f32();
+            let n = (-2.0 * u.ln()).sqrt() * (std::f32::consts::TAU * v).cos();
+            return (lambda + lambda.sqrt() * n).round().max(0.0) as u32;
+        }
+        let limit = (-lambda).exp();
+        let mut k = 0;
+        let mut p = self.next_f32();
+        while p > limit {
+            k += 1;
+            p *= self.next_f32();
+        }
+        k
+    }
+}
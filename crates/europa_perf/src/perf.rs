@@ -20,9 +20,9 @@ fn log_fps(d: Res<DiagnosticsStore>, time: Res<Time>, mut timer: Local<Timer>) {
         return;
     }
 
-    if let Some(diag) = d.get(&FrameTimeDiagnosticsPlugin::FPS) {
-        if let Some(fps) = diag.smoothed().or_else(|| diag.average()) {
-            println!("fps ~ {:.1}", fps);
-        }
+    if let Some(diag) = d.get(&FrameTimeDiagnosticsPlugin::FPS)
+        && let Some(fps) = diag.smoothed().or_else(|| diag.average())
+    {
+        println!("fps ~ {:.1}", fps);
     }
 }
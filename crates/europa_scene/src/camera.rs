@@ -1,26 +1,36 @@
 use bevy::input::mouse::MouseMotion;
 use bevy::prelude::*;
+use europa_terrain::globe::{Globe, GlobeSampler};
+use europa_terrain::{TerrainPicked, TerrainSampler, WorldOrigin};
 
 #[derive(Resource, Default)]
 struct CamLock {
     mode: LockMode,
+    movement: MoveMode,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+/// how wasd moves the camera, independent of what it's locked onto
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+enum MoveMode {
+    #[default]
+    Fly,
+    /// standing on the ice at eye height, under gravity
+    Walk,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
 enum LockMode {
+    #[default]
     Free,
     Sun,
     // future things
 }
 
-impl Default for LockMode {
-    fn default() -> Self {
-        LockMode::Free
-    }
+pub struct CameraPlugin {
+    /// orbit camera over the cube-sphere instead of the flat patch controls
+    pub globe: bool,
 }
 
-pub struct CameraPlugin;
-
 #[derive(Component)]
 struct FlyCam {
     yaw: f32,
@@ -29,19 +39,58 @@ struct FlyCam {
     sensitivity: f32,
 }
 
+/// ground mode state and tuning
+#[derive(Component)]
+struct Walker {
+    eye_height: f32,
+    /// m/s, shift runs at `run_factor` times this (rover speeds)
+    speed: f32,
+    run_factor: f32,
+    /// steepest ground that can be walked up, radians
+    max_slope: f32,
+    /// takeoff speed, m/s
+    jump_speed: f32,
+    /// ground this far below the feet is stepped down onto rather than fallen to
+    step_down: f32,
+    vertical_speed: f32,
+    grounded: bool,
+}
+
+use crate::constants::{EUROPA_GRAVITY, GLOBE_FAR};
 use crate::sky::SkySettings;
 
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<CamLock>()
-            .add_systems(Startup, spawn_camera)
-            .add_systems(Update, (toggle_lock, mouse_look, kb_move, lock_aim_update));
+            .add_systems(Update, (toggle_lock, lock_aim_update));
+
+        if self.globe {
+            app.add_systems(Startup, spawn_orbit_camera)
+                .add_systems(Update, (globe_look, globe_fly).chain());
+        } else {
+            app.add_systems(Startup, spawn_camera).add_systems(
+                Update,
+                (mouse_look, toggle_walk, kb_move, walk_move, log_picks),
+            );
+        }
     }
 }
 
-fn spawn_camera(mut commands: Commands) {
+fn spawn_camera(commands: Commands) {
     let mut t = Transform::from_xyz(0.0, 20.0, 8.0);
     t.look_at(Vec3::new(0.0, 20.0, 0.0), Vec3::Y);
+    spawn_camera_at(commands, t, 50_000.0);
+}
+
+/// a couple of radii out, looking at the globe centre
+fn spawn_orbit_camera(commands: Commands, globe: Res<Globe>) {
+    let r = globe.radius;
+    let mut t = Transform::from_translation(globe.center + Vec3::new(0.0, 1.5 * r, 2.5 * r));
+    t.look_at(globe.center, Vec3::Y);
+    spawn_camera_at(commands, t, GLOBE_FAR);
+}
+
+fn spawn_camera_at(mut commands: Commands, t: Transform, far: f32) {
     let (yaw, pitch, _roll) = t.rotation.to_euler(EulerRot::YXZ);
 
     commands.spawn((
@@ -54,7 +103,7 @@ fn spawn_camera(mut commands: Commands) {
         t,
         Projection::Perspective(PerspectiveProjection {
             near: 0.1,
-            far: 50_000.0,
+            far,
             fov: std::f32::consts::FRAC_PI_3,
             ..default()
         }),
@@ -64,6 +113,16 @@ fn spawn_camera(mut commands: Commands) {
             speed: 20.0,
             sensitivity: 0.002,
         },
+        Walker {
+            eye_height: 1.7,
+            speed: 2.0,
+            run_factor: 5.0,
+            max_slope: 30_f32.to_radians(),
+            jump_speed: 2.0,
+            step_down: 0.5,
+            vertical_speed: 0.0,
+            grounded: false,
+        },
     ));
 }
 
@@ -84,6 +143,26 @@ fn toggle_lock(keys: Res<ButtonInput<KeyCode>>, mut lock: ResMut<CamLock>) {
     }
 }
 
+fn toggle_walk(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut lock: ResMut<CamLock>,
+    mut q: Query<&mut Walker>,
+) {
+    if !keys.just_pressed(KeyCode::KeyG) {
+        return;
+    }
+    lock.movement = match lock.movement {
+        MoveMode::Fly => MoveMode::Walk,
+        MoveMode::Walk => MoveMode::Fly,
+    };
+    // drop from wherever the camera was
+    if let Ok(mut w) = q.single_mut() {
+        w.vertical_speed = 0.0;
+        w.grounded = false;
+    }
+    info!("Camera movement: {:?}", lock.movement);
+}
+
 fn mouse_look(
     mut ev: MessageReader<MouseMotion>,
     buttons: Res<ButtonInput<MouseButton>>,
@@ -106,11 +185,19 @@ fn mouse_look(
     t.rotation = Quat::from_euler(EulerRot::YXZ, c.yaw, c.pitch, 0.0);
 }
 
+/// closest the free camera gets to the ground, meters
+const GROUND_CLEARANCE: f32 = 1.5;
+
 fn kb_move(
     time: Res<Time>,
     keys: Res<ButtonInput<KeyCode>>,
+    lock: Res<CamLock>,
+    terrain: TerrainSampler,
     mut q: Query<(&mut Transform, &FlyCam)>,
 ) {
+    if lock.movement != MoveMode::Fly {
+        return;
+    }
     let (mut t, c) = q.single_mut().unwrap();
     let mut v = Vec3::ZERO;
 
@@ -142,6 +229,167 @@ fn kb_move(
     if v.length_squared() > 0.0 {
         t.translation += v.normalize() * c.speed * boost * time.delta_secs();
     }
+
+    // never sink below the surface, also when the terrain regenerates underneath
+    let ground = terrain.height(t.translation.x, t.translation.z) + GROUND_CLEARANCE;
+    t.translation.y = t.translation.y.max(ground);
+}
+
+fn walk_move(
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    lock: Res<CamLock>,
+    terrain: TerrainSampler,
+    mut q: Query<(&mut Transform, &mut Walker)>,
+) {
+    if lock.movement != MoveMode::Walk {
+        return;
+    }
+    let Ok((mut t, mut w)) = q.single_mut() else {
+        return;
+    };
+    let dt = time.delta_secs();
+
+    // wasd along the ground, whatever the pitch
+    let forward = t.forward().with_y(0.0).normalize_or_zero();
+    let right = t.right().with_y(0.0).normalize_or_zero();
+    let mut v = Vec3::ZERO;
+    if keys.pressed(KeyCode::KeyW) {
+        v += forward;
+    }
+    if keys.pressed(KeyCode::KeyS) {
+        v -= forward;
+    }
+    if keys.pressed(KeyCode::KeyA) {
+        v -= right;
+    }
+    if keys.pressed(KeyCode::KeyD) {
+        v += right;
+    }
+    let run = if keys.pressed(KeyCode::ShiftLeft) {
+        w.run_factor
+    } else {
+        1.0
+    };
+
+    if v.length_squared() > 0.0 {
+        let step = v.normalize() * w.speed * run * dt;
+        let (x, z) = (t.translation.x + step.x, t.translation.z + step.z);
+        // too steep to climb: stay put, but walking down or across is fine
+        let here = terrain.height(t.translation.x, t.translation.z);
+        let there = terrain.sample(x, z);
+        let blocked = w.grounded && there.slope > w.max_slope && there.height > here;
+        if !blocked {
+            t.translation.x = x;
+            t.translation.z = z;
+        }
+    }
+
+    if w.grounded && keys.just_pressed(KeyCode::Space) {
+        w.vertical_speed = w.jump_speed;
+        w.grounded = false;
+    }
+
+    let ground = terrain.height(t.translation.x, t.translation.z);
+    let feet = t.translation.y - w.eye_height;
+    if w.grounded && feet - ground <= w.step_down {
+        // follow the ground down slopes instead of floating off them in the low gravity
+        t.translation.y = ground + w.eye_height;
+        return;
+    }
+
+    w.vertical_speed -= EUROPA_GRAVITY * dt;
+    t.translation.y += w.vertical_speed * dt;
+    if t.translation.y - w.eye_height <= ground {
+        t.translation.y = ground + w.eye_height;
+        w.vertical_speed = 0.0;
+        w.grounded = true;
+    } else {
+        w.grounded = false;
+    }
+}
+
+/// mouse look over the globe. yaw turns about the local vertical and pitch is
+/// measured from the local horizon, and the view is re-levelled every frame as
+/// the vertical swings round with the camera, so the horizon never rolls
+fn globe_look(
+    mut ev: MessageReader<MouseMotion>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    globe: GlobeSampler,
+    mut q: Query<(&mut Transform, &FlyCam)>,
+) {
+    let Ok((mut t, c)) = q.single_mut() else {
+        return;
+    };
+    let up = globe.up(t.translation);
+    let forward = *t.forward();
+
+    // heading on the local horizon; straight up or down it falls back to
+    // whatever the top of the screen points at
+    let flat = |v: Vec3| v - up * v.dot(up);
+    let mut heading = flat(forward)
+        .try_normalize()
+        .or_else(|| flat(*t.up()).try_normalize())
+        .unwrap_or_else(|| up.any_orthonormal_vector());
+    let mut pitch = forward.dot(up).clamp(-1.0, 1.0).asin();
+
+    let delta: Vec2 = ev.read().map(|m| m.delta).sum();
+    if buttons.pressed(MouseButton::Right) {
+        heading = Quat::from_axis_angle(up, -delta.x * c.sensitivity) * heading;
+        pitch -= delta.y * c.sensitivity;
+    }
+    let pitch = pitch.clamp(-1.54, 1.54);
+    t.look_to(heading * pitch.cos() + up * pitch.sin(), up);
+}
+
+/// free flight over the globe: up/down follow the local vertical and speed
+/// scales with altitude, so orbit to surface takes seconds either way
+fn globe_fly(
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    globe: GlobeSampler,
+    mut q: Query<(&mut Transform, &FlyCam)>,
+) {
+    let Ok((mut t, c)) = q.single_mut() else {
+        return;
+    };
+    let up = globe.up(t.translation);
+    let mut v = Vec3::ZERO;
+    if keys.pressed(KeyCode::KeyW) {
+        v += *t.forward();
+    }
+    if keys.pressed(KeyCode::KeyS) {
+        v -= *t.forward();
+    }
+    if keys.pressed(KeyCode::KeyA) {
+        v += *t.left();
+    }
+    if keys.pressed(KeyCode::KeyD) {
+        v += *t.right();
+    }
+    if keys.pressed(KeyCode::Space) {
+        v += up;
+    }
+    if keys.pressed(KeyCode::ControlLeft) {
+        v -= up;
+    }
+
+    let boost = if keys.pressed(KeyCode::ShiftLeft) {
+        10.0
+    } else {
+        1.0
+    };
+    let altitude = globe.altitude(t.translation);
+    let speed = c.speed.max(0.5 * altitude);
+    if v.length_squared() > 0.0 {
+        t.translation += v.normalize() * speed * boost * time.delta_secs();
+    }
+
+    let below = globe.altitude(t.translation) - GROUND_CLEARANCE;
+    if below < 0.0 {
+        let up = globe.up(t.translation);
+        t.translation -= up * below;
+    }
 }
 
 fn lock_aim_update(
@@ -166,3 +414,22 @@ fn lock_aim_update(
         LockMode::Free => {}
     }
 }
+
+fn log_picks(
+    mut picked: MessageReader<TerrainPicked>,
+    terrain: TerrainSampler,
+    origin: Res<WorldOrigin>,
+) {
+    for p in picked.read() {
+        let s = terrain.sample(p.hit.position.x, p.hit.position.z);
+        let at = origin.to_world(p.hit.position);
+        info!(
+            "Picked ({:.1}, {:.2}, {:.1}) at {:.1} m, slope {:.1}°",
+            at.x,
+            at.y,
+            at.z,
+            p.hit.distance,
+            s.slope.to_degrees()
+        );
+    }
+}
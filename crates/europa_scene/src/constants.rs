@@ -2,3 +2,6 @@ pub const SKY_RADIUS: f32 = 10_000.0; // arbitrary big bubble around the player
 pub const SUN_ANGULAR_DIAMETER_DEG: f32 = 0.10; // ~0.5° / 5.2 AU
 pub const JUPITER_ANGULAR_DIAMETER_DEG: f32 = 11.9; // ~size from Europa (deg)
 pub const JUPITER_OBLIQUITY_DEG: f32 = 3.13;
+pub const EUROPA_GRAVITY: f32 = 1.315; // surface gravity (m/s²)
+pub const GLOBE_SKY_RADIUS: f32 = 1.5e7; // clears Europa (r ~1561 km) from a few radii out
+pub const GLOBE_FAR: f32 = 4.0e7; // camera far plane in globe mode, past the star dome
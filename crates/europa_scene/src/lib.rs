@@ -1,20 +1,41 @@
 use bevy::prelude::*;
-use europa_terrain::TerrainPlugin;
+use europa_terrain::globe::GlobePlugin;
+use europa_terrain::{EUROPA_RADIUS, FloatingOriginPlugin, TerrainPlugin};
 
 mod camera;
 mod constants;
 mod sky;
 mod timeflow;
 
-pub struct ScenePlugin;
+#[derive(Default)]
+pub struct ScenePlugin {
+    /// the whole moon as a cube-sphere instead of the flat local patch
+    pub globe: bool,
+}
 
 impl Plugin for ScenePlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins((
             timeflow::TimeFlowPlugin,
-            camera::CameraPlugin,
+            camera::CameraPlugin { globe: self.globe },
             sky::SkyPlugin,
-            TerrainPlugin::europa_default(),
+            // keeps the camera near the render origin, for long walks and the globe
+            FloatingOriginPlugin::default(),
         ));
+
+        if self.globe {
+            // the sky has to sit outside the whole moon seen from orbit
+            app.insert_resource(sky::SkySettings {
+                sky_radius: constants::GLOBE_SKY_RADIUS,
+                ..default()
+            })
+            .add_plugins(GlobePlugin::europa_default());
+        } else {
+            app.add_plugins(
+                TerrainPlugin::europa_default()
+                    .with_recipe("terrain/europa.height.ron")
+                    .curved(EUROPA_RADIUS),
+            );
+        }
     }
 }
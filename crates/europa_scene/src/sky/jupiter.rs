@@ -1,4 +1,4 @@
-use crate::constants::{JUPITER_ANGULAR_DIAMETER_DEG, JUPITER_OBLIQUITY_DEG, SKY_RADIUS};
+use crate::constants::{JUPITER_ANGULAR_DIAMETER_DEG, JUPITER_OBLIQUITY_DEG};
 use crate::sky::SkySettings;
 use bevy::camera::visibility::NoFrustumCulling;
 use bevy::prelude::*;
@@ -32,7 +32,7 @@ fn spawn_jupiter(
         ..default()
     });
 
-    let sphere_mesh = meshes.add(Mesh::from(Sphere::new(1.0).mesh().uv(64, 32)));
+    let sphere_mesh = meshes.add(Sphere::new(1.0).mesh().uv(64, 32));
 
     commands.spawn((
         Mesh3d(sphere_mesh),
@@ -44,6 +44,7 @@ fn spawn_jupiter(
     ));
 }
 
+#[allow(clippy::type_complexity)]
 fn place_and_scale_jupiter(
     settings: Res<SkySettings>,
     cam_q: Query<(&Transform, &Projection), (With<Camera3d>, Without<Jupiter>)>,
@@ -61,7 +62,7 @@ fn place_and_scale_jupiter(
         Projection::Orthographic(o) => o.far,
         _ => return,
     };
-    let sky_r = (far * 0.85).min(SKY_RADIUS);
+    let sky_r = (far * 0.85).min(settings.sky_radius);
 
     let dir = settings.base_jupiter_dir.normalize_or_zero();
     t.translation = cam_t.translation + dir * sky_r;
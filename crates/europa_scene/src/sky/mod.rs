@@ -1,4 +1,5 @@
 use super::timeflow::{SimSet, SimTime};
+use crate::constants::SKY_RADIUS;
 use bevy::prelude::*;
 use europa_math::smoothstep;
 use std::f32::consts::PI;
@@ -32,6 +33,9 @@ pub struct SkySettings {
     pub jupiter_ang_radius: f32,
     pub planetshine_max: f32,
     pub eclipse_soft: f32,
+    /// how far out the sun/jupiter discs and the star dome sit around the
+    /// camera, must clear anything rendered (the whole globe in orbit)
+    pub sky_radius: f32,
 }
 
 impl Default for SkySettings {
@@ -49,6 +53,7 @@ impl Default for SkySettings {
             jupiter_ang_radius: 12.0_f32.to_radians() * 0.5,
             planetshine_max: 0.006,
             eclipse_soft: 1.0_f32.to_radians(),
+            sky_radius: SKY_RADIUS,
         }
     }
 }
@@ -107,12 +112,3 @@ pub fn animate_sky_physical(
 
     settings.base_sun_dir = sun_dir;
 }
-
-trait Reject {
-    fn reject_from(self, n: Vec3) -> Vec3;
-}
-impl Reject for Vec3 {
-    fn reject_from(self, n: Vec3) -> Vec3 {
-        self - self.project_onto(n)
-    }
-}
@@ -23,7 +23,7 @@ fn spawn_starfield(
 ) {
     let tex: Handle<Image> = asset_server.load("sky/Starfield.jpg");
 
-    let dome_mesh = meshes.add(Mesh::from(Sphere::new(1.0).mesh().uv(128, 64)));
+    let dome_mesh = meshes.add(Sphere::new(1.0).mesh().uv(128, 64));
     let dome_mat = mats.add(StandardMaterial {
         base_color_texture: Some(tex),
         unlit: true,
@@ -43,6 +43,7 @@ fn spawn_starfield(
 }
 
 fn track_camera(
+    settings: Res<SkySettings>,
     cam_q: Query<&Transform, (With<Camera3d>, Without<StarDome>)>,
     mut dome_q: Query<&mut Transform, (With<StarDome>, Without<Camera3d>)>,
 ) {
@@ -55,9 +56,12 @@ fn track_camera(
 
     // lock pos to camera so it never parallax-shifts
     t.translation = cam.translation;
+    // just behind the sun and jupiter discs
+    t.scale = Vec3::splat(2.0 * settings.sky_radius);
 }
 
 // fade stars when the sun is near the view center... cheap "glare" ;)
+#[allow(clippy::type_complexity)]
 fn dim_stars_near_sun(
     settings: Res<SkySettings>,
     cam_q: Query<(&Transform, &Projection), (With<Camera3d>, Without<StarDome>)>,
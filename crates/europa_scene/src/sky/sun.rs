@@ -1,4 +1,4 @@
-use crate::constants::{SKY_RADIUS, SUN_ANGULAR_DIAMETER_DEG};
+use crate::constants::SUN_ANGULAR_DIAMETER_DEG;
 use crate::sky::{SkySettings, SkyState};
 use crate::timeflow::SimSet;
 use bevy::camera::visibility::NoFrustumCulling;
@@ -58,7 +58,7 @@ fn spawn_sun(
         ..default()
     });
 
-    let disc_mesh = meshes.add(Mesh::from(Sphere::new(1.0).mesh().uv(32, 16)));
+    let disc_mesh = meshes.add(Sphere::new(1.0).mesh().uv(32, 16));
 
     // visible disc: unlit sphere scaled to angular size, always far away
     commands.spawn((
@@ -91,6 +91,7 @@ fn update_sun_light(
     }
 }
 
+#[allow(clippy::type_complexity)]
 fn position_sun_disc(
     settings: Res<SkySettings>,
     cam_q: Query<(&Transform, &Projection), (With<Camera3d>, Without<SunDisc>)>,
@@ -105,7 +106,7 @@ fn position_sun_disc(
         _ => return,
     };
 
-    let sky_r = (far * 0.85).min(SKY_RADIUS); // inside clip range
+    let sky_r = (far * 0.85).min(settings.sky_radius); // inside clip range
 
     let dir_to_sun = settings.base_sun_dir.normalize();
     let mut t = disc_q.single_mut().unwrap();
@@ -0,0 +1,349 @@
+use std::path::Path;
+
+use bevy::asset::{AssetLoader, LoadContext, io::Reader};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::height::recipe::HeightRecipe;
+
+/// terrain recipe file (`*.height.ron` / `*.height.toml`)
+#[derive(Asset, TypePath, Serialize, Deserialize, Clone, Debug)]
+pub struct TerrainRecipe {
+    pub height: HeightRecipe,
+}
+
+impl TerrainRecipe {
+    /// reads a recipe outside the asset server (tools, CLI)
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, RecipeError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        Self::parse(&text, path)
+    }
+
+    /// TOML if `path` ends in `.toml`, RON otherwise
+    fn parse(text: &str, path: &Path) -> Result<Self, RecipeError> {
+        if path.extension().is_some_and(|ext| ext == "toml") {
+            Ok(toml::from_str(text)?)
+        } else {
+            Ok(ron::from_str(text)?)
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RecipeError {
+    #[error("could not read recipe: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("recipe is not valid utf-8: {0}")]
+    Utf8(#[from] std::str::Utf8Error),
+    #[error("bad RON recipe: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("bad TOML recipe: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("invalid recipe: {0}")]
+    Invalid(String),
+}
+
+#[derive(Default)]
+pub struct TerrainRecipeLoader;
+
+impl AssetLoader for TerrainRecipeLoader {
+    type Asset = TerrainRecipe;
+    type Settings = ();
+    type Error = RecipeError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<TerrainRecipe, RecipeError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let text = std::str::from_utf8(&bytes)?;
+        TerrainRecipe::parse(text, load_context.path())
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["height.ron", "height.toml"]
+    }
+}
+
+/// the recipe the terrain is currently following
+#[derive(Resource)]
+pub(crate) struct RecipeHandle(pub Handle<TerrainRecipe>);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn params_reshape_the_shipped_recipe() {
+        use crate::systems::{apply_curvature, apply_recipe};
+        use crate::{BaseHeight, HeightResource, TerrainParams};
+
+        let params = TerrainParams::europa_demo();
+        let text = include_str!("../../../assets/terrain/europa.height.ron");
+        let recipe: TerrainRecipe = ron::from_str(text).unwrap();
+        let start = recipe.height.build(&params).unwrap();
+
+        let mut app = App::new();
+        app.init_resource::<Assets<TerrainRecipe>>()
+            .add_message::<AssetEvent<TerrainRecipe>>()
+            .insert_resource(params)
+            .insert_resource(BaseHeight(start.clone()))
+            .insert_resource(HeightResource(start))
+            .add_systems(Update, (apply_recipe, apply_curvature).chain());
+        let handle = app
+            .world_mut()
+            .resource_mut::<Assets<TerrainRecipe>>()
+            .add(recipe);
+        app.insert_resource(RecipeHandle(handle));
+        app.update();
+
+        let rendered = |app: &App, x, z| app.world().resource::<HeightResource>().0.height_at(x, z);
+        let points = [(0.0, 0.0), (120.0, -40.0), (-900.0, 1300.0)];
+        let before = points.map(|(x, z)| rendered(&app, x, z));
+
+        app.world_mut().resource_mut::<TerrainParams>().amp *= 2.0;
+        app.update();
+        for ((x, z), h) in points.into_iter().zip(before) {
+            let after = rendered(&app, x, z);
+            assert!(
+                (after - 2.0 * h).abs() < 1e-4,
+                "{h} -> {after} at ({x}, {z})"
+            );
+        }
+
+        app.world_mut().resource_mut::<TerrainParams>().line_dir = Vec2::Y;
+        app.update();
+        let turned = points.map(|(x, z)| rendered(&app, x, z));
+        assert!(
+            turned
+                .iter()
+                .zip(before)
+                .any(|(t, h)| (t - 2.0 * h).abs() > 1e-2),
+            "line_dir left the surface alone"
+        );
+
+        // everything sits in base wavelengths, so a doubled freq halves the map
+        let far = points.map(|(x, z)| rendered(&app, 2.0 * x, 2.0 * z));
+        app.world_mut().resource_mut::<TerrainParams>().freq *= 2.0;
+        app.update();
+        for ((x, z), h) in points.into_iter().zip(far) {
+            let after = rendered(&app, x, z);
+            assert!((after - h).abs() < 1e-3, "{h} -> {after} at ({x}, {z})");
+        }
+    }
+
+    #[test]
+    fn shipped_recipe_spells_out_the_generator() {
+        let params = crate::TerrainParams::europa_demo();
+        let text = include_str!("../../../assets/terrain/europa.height.ron");
+        let recipe: TerrainRecipe = ron::from_str(text).unwrap();
+        let shipped = recipe.height.build(&params).unwrap();
+        let generated = crate::europa_height(&params);
+        for (x, z) in [(0.0, 0.0), (120.0, -40.0), (-900.0, 1300.0), (2500.0, 77.0)] {
+            let (a, b) = (shipped.height_at(x, z), generated.height_at(x, z));
+            assert!((a - b).abs() < 1e-3, "{a} vs {b} at ({x}, {z})");
+        }
+    }
+
+    #[test]
+    fn toml_recipe_parses() {
+        let text = r#"
+            [height.Bias]
+            bias = -0.5
+
+            [height.Bias.s.PerlinFbm]
+            freq = 0.002
+            octaves = 3
+            lacunarity = 2.0
+            gain = 0.5
+            amplitude = 4.0
+        "#;
+        let recipe: TerrainRecipe = toml::from_str(text).unwrap();
+        assert!(
+            recipe
+                .height
+                .build(&crate::TerrainParams::europa_demo())
+                .unwrap()
+                .height_at(0.0, 0.0)
+                .is_finite()
+        );
+    }
+
+    #[test]
+    fn worley_plates_parse() {
+        let text = r#"(
+            height: Fbm(
+                basis: Worley(mode: F2MinusF1),
+                fractal: (freq: 0.001, octaves: 2, lacunarity: 2.0, gain: 0.5, amplitude: 3.0),
+            ),
+        )"#;
+        let recipe: TerrainRecipe = ron::from_str(text).unwrap();
+        let h = recipe
+            .height
+            .build(&crate::TerrainParams::europa_demo())
+            .unwrap();
+        assert!((0.0..=3.0).contains(&h.height_at(250.0, -80.0)));
+    }
+
+    #[test]
+    fn craters_without_a_diameter_range_are_rejected() {
+        let craters = |d_min: f32, d_max: f32| {
+            format!(
+                "(height: Craters(density: 2.0, d_min: {d_min:?}, d_max: {d_max:?}, slope: 2.0, \
+                 depth_ratio: 0.15, rim_ratio: 0.035, complex_diameter: 4000.0, ejecta_extent: 3.0))"
+            )
+        };
+        for (lo, hi) in [
+            (0.0, 1500.0),
+            (-5.0, 1500.0),
+            (1500.0, 20.0),
+            (20.0, f32::INFINITY),
+        ] {
+            let recipe: TerrainRecipe = ron::from_str(&craters(lo, hi)).unwrap();
+            assert!(
+                matches!(
+                    recipe.height.build(&crate::TerrainParams::europa_demo()),
+                    Err(RecipeError::Invalid(_))
+                ),
+                "d_min {lo}, d_max {hi} was accepted"
+            );
+        }
+        let recipe: TerrainRecipe = ron::from_str(&craters(20.0, 1500.0)).unwrap();
+        assert!(
+            recipe
+                .height
+                .build(&crate::TerrainParams::europa_demo())
+                .is_ok()
+        );
+    }
+
+    fn assert_invalid(text: &str) {
+        let recipe: TerrainRecipe = ron::from_str(text).unwrap();
+        assert!(
+            matches!(
+                recipe.height.build(&crate::TerrainParams::europa_demo()),
+                Err(RecipeError::Invalid(_))
+            ),
+            "{text} was accepted"
+        );
+    }
+
+    #[test]
+    fn fractals_need_octaves_and_positive_frequencies() {
+        let fbm = |freq: f32, octaves: u32, lacunarity: f32, gain: f32| {
+            format!(
+                "(height: PerlinFbm(freq: {freq:?}, octaves: {octaves}, lacunarity: {lacunarity:?}, \
+                 gain: {gain:?}, amplitude: 4.0))"
+            )
+        };
+        for text in [
+            fbm(0.002, 0, 2.0, 0.5),
+            fbm(0.002, 1000, 2.0, 0.5),
+            fbm(0.0, 3, 2.0, 0.5),
+            fbm(f32::NAN, 3, 2.0, 0.5),
+            fbm(0.002, 3, -2.0, 0.5),
+            fbm(0.002, 3, 2.0, 0.0),
+        ] {
+            assert_invalid(&text);
+        }
+        assert_invalid(
+            "(height: PerlinRidged(freq: -0.01, octaves: 3, lacunarity: 2.0, gain: 0.5, \
+             amplitude: 4.0, z_anisotropy: 1.0))",
+        );
+        for node in ["Fbm", "Ridged", "Billow"] {
+            assert_invalid(&format!(
+                "(height: {node}(basis: Perlin, fractal: (freq: 0.001, octaves: 0, lacunarity: 2.0, \
+                 gain: 0.5, amplitude: 3.0)))"
+            ));
+        }
+        assert_invalid(
+            "(height: HybridMulti(basis: Perlin, fractal: (freq: 0.001, octaves: 4, \
+             lacunarity: 2.0, gain: -1.0, amplitude: 3.0), offset: 0.7))",
+        );
+    }
+
+    #[test]
+    fn warp_needs_octaves_and_a_positive_frequency() {
+        let warp = |warp_freq: f32, octaves: u32| {
+            format!(
+                "(height: Warp2D(source: PerlinFbm(freq: 0.002, octaves: 3, lacunarity: 2.0, \
+                 gain: 0.5, amplitude: 4.0), warp_amp: 300.0, warp_freq: {warp_freq:?}, \
+                 octaves: {octaves}, lacunarity: 2.0, gain: 0.5))"
+            )
+        };
+        for (freq, octaves) in [(0.001, 0), (0.0, 3), (f32::INFINITY, 3)] {
+            assert_invalid(&warp(freq, octaves));
+        }
+        let recipe: TerrainRecipe = ron::from_str(&warp(0.001, 3)).unwrap();
+        let h = recipe
+            .height
+            .build(&crate::TerrainParams::europa_demo())
+            .unwrap();
+        assert!(h.height_at(120.0, 40.0).is_finite());
+    }
+
+    #[test]
+    fn features_need_positive_sizes() {
+        const FLAT: &str = "Bias(s: PerlinFbm(freq: 0.002, octaves: 1, lacunarity: 2.0, gain: 0.5, \
+                            amplitude: 0.0), bias: 0.0)";
+        assert_invalid(&format!(
+            "(height: ChaosTerrain(source: {FLAT}, raft_size: 0.0, raft_fraction: 0.6, gap: 0.1, \
+             edge: 0.1, max_shift: 50.0, max_rotation: 0.3, max_tilt: 0.05, max_lift: 20.0, \
+             matrix_depth: 30.0, hummock_amp: 5.0, hummock_freq: 0.01))"
+        ));
+        for (length, width) in [
+            ((0.0, 20000.0), (2000.0, 8000.0)),
+            ((8000.0, 20000.0), (9000.0, 2000.0)),
+        ] {
+            assert_invalid(&format!(
+                "(height: Bands(source: {FLAT}, density: 1.0, length_min: {:?}, length_max: {:?}, \
+                 width_min: {:?}, width_max: {:?}, shear: 500.0, floor_height: -20.0, edge: 0.1, \
+                 groove_amp: 2.0, groove_spacing: 300.0))",
+                length.0, length.1, width.0, width.1
+            ));
+            assert_invalid(&format!(
+                "(height: DoubleRidges(density: 1.0, length_min: {:?}, length_max: {:?}, \
+                 width_min: {:?}, width_max: {:?}, height_ratio: 0.1, trough: 0.3, curvature: 0.2, \
+                 wiggle: 0.1, segments: 8))",
+                length.0, length.1, width.0, width.1
+            ));
+        }
+        assert_invalid(
+            "(height: Lenticulae(density: 1.0, diameter_min: 12000.0, diameter_max: 5000.0, \
+             dome_weight: 1.0, pit_weight: 1.0, chaos_weight: 1.0, dome_height: 100.0, \
+             pit_depth: 80.0, chaos_depth: 60.0, dome_profile: Gaussian, pit_profile: Cosine, \
+             chaos_roughness: 5.0, chaos_freq: 0.01, max_elongation: 1.5))",
+        );
+    }
+
+    #[test]
+    fn clamp_bounds_must_be_ordered_and_finite() {
+        let clamp = |min: f32, max: f32| {
+            format!(
+                "(height: Clamp(s: PerlinFbm(freq: 0.002, octaves: 3, lacunarity: 2.0, gain: 0.5, \
+                 amplitude: 4.0), min: {min:?}, max: {max:?}))"
+            )
+        };
+        for (min, max) in [(2.0, -2.0), (f32::NAN, 1.0), (-1.0, f32::INFINITY)] {
+            let recipe: TerrainRecipe = ron::from_str(&clamp(min, max)).unwrap();
+            assert!(
+                matches!(
+                    recipe.height.build(&crate::TerrainParams::europa_demo()),
+                    Err(RecipeError::Invalid(_))
+                ),
+                "min {min}, max {max} was accepted"
+            );
+        }
+        let recipe: TerrainRecipe = ron::from_str(&clamp(-1.0, 1.0)).unwrap();
+        let h = recipe
+            .height
+            .build(&crate::TerrainParams::europa_demo())
+            .unwrap();
+        assert!((-1.0..=1.0).contains(&h.height_at(120.0, 40.0)));
+    }
+}
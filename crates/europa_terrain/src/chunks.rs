@@ -0,0 +1,364 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use bevy::ecs::query::QueryFilter;
+use bevy::prelude::*;
+use bevy::tasks::{AsyncComputeTaskPool, Task, futures::check_ready};
+
+use crate::origin::NoRebase;
+
+/// a full-resolution chunk build, run on the async compute pool and handed
+/// the cancel check
+pub(crate) type ChunkBuild = Box<dyn FnOnce(&(dyn Fn() -> bool + Sync)) -> Option<Mesh> + Send>;
+
+/// how a streamed surface lays out and builds its chunks, for the current frame
+pub(crate) trait ChunkLayout {
+    type Key: Copy + Eq + Hash + Send + Sync + 'static;
+
+    /// render-space translation of the chunk entity, its mesh is relative to it
+    fn translation(&self, key: &Self::Key) -> Vec3;
+
+    /// coarse mesh shown until the full build lands, built on the spot
+    fn placeholder(&self, key: &Self::Key) -> Mesh;
+
+    fn build(&self, key: &Self::Key) -> ChunkBuild;
+
+    /// marker and name for a new chunk entity
+    fn tag(&self, key: &Self::Key) -> impl Bundle;
+}
+
+/// per-frame limits on chunk work
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ChunkBudget {
+    pub placeholders_per_frame: usize,
+    pub builds_per_frame: usize,
+    pub max_in_flight: usize,
+}
+
+/// chunk entities of one streamed surface. placeholders go up at once, full
+/// builds run on the async compute pool and swap in when done
+#[derive(Resource)]
+pub(crate) struct ChunkSet<K: Send + Sync + 'static> {
+    root: Entity,
+    material: Handle<StandardMaterial>,
+    loaded: HashMap<K, LoadedChunk>,
+    /// full-resolution builds running on the async compute pool
+    pending: HashMap<K, PendingChunk>,
+    /// bumped whenever the surface changes; chunks built earlier are stale
+    generation: u32,
+}
+
+struct LoadedChunk {
+    entity: Entity,
+    generation: u32,
+    /// still showing the low-res placeholder
+    placeholder: bool,
+}
+
+struct PendingChunk {
+    task: Task<Option<Mesh>>,
+    generation: u32,
+    cancel: Arc<AtomicBool>,
+}
+
+impl PendingChunk {
+    /// stops the build at its next row band; dropping the task stops it if it never started
+    fn cancel(self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+impl<K: Copy + Eq + Hash + Send + Sync + 'static> ChunkSet<K> {
+    /// spawns the parent entity the chunks hang off, with the icy material they share
+    pub fn spawn(
+        commands: &mut Commands,
+        mats: &mut Assets<StandardMaterial>,
+        name: &'static str,
+    ) -> Self {
+        let material = mats.add(StandardMaterial {
+            // pale, icy
+            base_color: Color::srgb(0.78, 0.83, 0.88),
+            perceptual_roughness: 1.0,
+            reflectance: 0.02,
+            metallic: 0.0,
+            alpha_mode: AlphaMode::Opaque,
+            ..default()
+        });
+        let root = commands
+            .spawn((
+                Transform::IDENTITY,
+                Visibility::default(),
+                Name::new(name),
+                NoRebase,
+            ))
+            .id();
+        Self {
+            root,
+            material,
+            loaded: HashMap::new(),
+            pending: HashMap::new(),
+            generation: 0,
+        }
+    }
+
+    /// the surface changed, everything loaded or building is stale
+    pub fn invalidate(&mut self) {
+        self.generation += 1;
+    }
+
+    /// swaps finished builds in over the placeholder or the stale mesh. the key
+    /// may sit elsewhere under the current layout, so the chunk is placed again too
+    pub fn finish_builds<L: ChunkLayout<Key = K>>(
+        &mut self,
+        commands: &mut Commands,
+        meshes: &mut Assets<Mesh>,
+        layout: &L,
+    ) {
+        let mut done = Vec::new();
+        for (key, pending) in self.pending.iter_mut() {
+            if let Some(mesh) = check_ready(&mut pending.task) {
+                done.push((*key, pending.generation, mesh));
+            }
+        }
+        for (key, built, mesh) in done {
+            self.pending.remove(&key);
+            let (Some(mesh), Some(chunk)) = (mesh, self.loaded.get_mut(&key)) else {
+                continue;
+            };
+            commands.entity(chunk.entity).insert((
+                Mesh3d(meshes.add(mesh)),
+                Transform::from_translation(layout.translation(&key)),
+            ));
+            chunk.generation = built;
+            chunk.placeholder = false;
+        }
+    }
+
+    /// brings the loaded set towards `wanted`, which is sorted nearest first
+    pub fn update<L: ChunkLayout<Key = K>>(
+        &mut self,
+        commands: &mut Commands,
+        meshes: &mut Assets<Mesh>,
+        layout: &L,
+        wanted: &[K],
+        budget: ChunkBudget,
+    ) {
+        let generation = self.generation;
+        let wanted_set: HashSet<K> = wanted.iter().copied().collect();
+
+        // builds for an older surface or for chunks nobody wants anymore are dropped
+        let cancelled: Vec<K> = self
+            .pending
+            .iter()
+            .filter(|(key, p)| p.generation != generation || !wanted_set.contains(key))
+            .map(|(key, _)| *key)
+            .collect();
+        for key in cancelled {
+            if let Some(pending) = self.pending.remove(&key) {
+                pending.cancel();
+            }
+        }
+
+        // new chunks go up as a coarse placeholder, a few per frame so a big
+        // jump doesn't stall one frame on hundreds of them
+        let missing: Vec<K> = wanted
+            .iter()
+            .filter(|k| !self.loaded.contains_key(k))
+            .copied()
+            .collect();
+        let cap = budget.placeholders_per_frame.max(1);
+        let covered = missing.len() <= cap;
+        for key in missing.iter().take(cap) {
+            let entity = commands
+                .spawn((
+                    Mesh3d(meshes.add(layout.placeholder(key))),
+                    MeshMaterial3d(self.material.clone()),
+                    Transform::from_translation(layout.translation(key)),
+                    layout.tag(key),
+                    ChildOf(self.root),
+                ))
+                .id();
+            self.loaded.insert(
+                *key,
+                LoadedChunk {
+                    entity,
+                    generation,
+                    placeholder: true,
+                },
+            );
+        }
+
+        // placeholders and chunks built from an older surface, nearest first
+        let work: Vec<K> = wanted
+            .iter()
+            .filter(|k| !self.pending.contains_key(k))
+            .filter(|k| {
+                self.loaded
+                    .get(k)
+                    .is_some_and(|c| c.placeholder || c.generation != generation)
+            })
+            .copied()
+            .collect();
+        let room = budget.max_in_flight.saturating_sub(self.pending.len());
+        let pool = AsyncComputeTaskPool::get();
+        for key in work
+            .into_iter()
+            .take(budget.builds_per_frame.max(1).min(room))
+        {
+            let cancel = Arc::new(AtomicBool::new(false));
+            let flag = cancel.clone();
+            let build = layout.build(&key);
+            let task = pool.spawn(async move { build(&|| flag.load(Ordering::Relaxed)) });
+            self.pending.insert(
+                key,
+                PendingChunk {
+                    task,
+                    generation,
+                    cancel,
+                },
+            );
+        }
+
+        // old chunks stay until every wanted chunk has at least a placeholder up,
+        // so nothing opens a hole
+        if !covered {
+            return;
+        }
+        self.loaded.retain(|key, chunk| {
+            let keep = wanted_set.contains(key);
+            if !keep {
+                commands.entity(chunk.entity).despawn();
+            }
+            keep
+        });
+    }
+
+    /// moves every loaded chunk to `translation`, after a rebase
+    pub fn place<F: QueryFilter>(
+        &self,
+        translation: impl Fn(&K) -> Vec3,
+        q: &mut Query<&mut Transform, F>,
+    ) {
+        for (key, chunk) in &self.loaded {
+            if let Ok(mut t) = q.get_mut(chunk.entity) {
+                t.translation = translation(key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::mesh::PrimitiveTopology;
+    use bevy::tasks::TaskPool;
+
+    use super::*;
+
+    /// chunks 10 m apart along x, `0` shifts them all like a new layout would
+    struct Row(f32);
+
+    impl ChunkLayout for Row {
+        type Key = u32;
+
+        fn translation(&self, key: &u32) -> Vec3 {
+            Vec3::X * (*key as f32 * 10.0 + self.0)
+        }
+
+        fn placeholder(&self, _: &u32) -> Mesh {
+            Mesh::new(PrimitiveTopology::TriangleList, default())
+        }
+
+        fn build(&self, _: &u32) -> ChunkBuild {
+            Box::new(|_| Some(Mesh::new(PrimitiveTopology::TriangleList, default())))
+        }
+
+        fn tag(&self, key: &u32) -> impl Bundle {
+            Name::new(format!("Chunk {key}"))
+        }
+    }
+
+    const BUDGET: ChunkBudget = ChunkBudget {
+        placeholders_per_frame: 16,
+        builds_per_frame: 64,
+        max_in_flight: 64,
+    };
+
+    fn world() -> World {
+        AsyncComputeTaskPool::get_or_init(TaskPool::default);
+        let mut world = World::new();
+        world.init_resource::<Assets<Mesh>>();
+        world.init_resource::<Assets<StandardMaterial>>();
+        world
+            .run_system_once(
+                |mut commands: Commands, mut mats: ResMut<Assets<StandardMaterial>>| {
+                    let chunks = ChunkSet::<u32>::spawn(&mut commands, &mut mats, "Chunks");
+                    commands.insert_resource(chunks);
+                },
+            )
+            .unwrap();
+        world
+    }
+
+    fn frame(world: &mut World, shift: f32, wanted: &[u32]) {
+        let wanted = wanted.to_vec();
+        world
+            .run_system_once(
+                move |mut commands: Commands,
+                      mut meshes: ResMut<Assets<Mesh>>,
+                      mut chunks: ResMut<ChunkSet<u32>>| {
+                    let layout = Row(shift);
+                    chunks.finish_builds(&mut commands, &mut meshes, &layout);
+                    chunks.update(&mut commands, &mut meshes, &layout, &wanted, BUDGET);
+                },
+            )
+            .unwrap();
+    }
+
+    /// frames until every build has landed
+    fn settle(world: &mut World, shift: f32, wanted: &[u32]) {
+        for _ in 0..1000 {
+            frame(world, shift, wanted);
+            if world.resource::<ChunkSet<u32>>().pending.is_empty() {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        panic!("builds never finished");
+    }
+
+    #[test]
+    fn placeholders_are_capped_and_old_chunks_wait_for_cover() {
+        let mut world = world();
+        settle(&mut world, 0.0, &[999]);
+
+        let wanted: Vec<u32> = (0..40).collect();
+        frame(&mut world, 0.0, &wanted);
+        let chunks = world.resource::<ChunkSet<u32>>();
+        assert_eq!(chunks.loaded.len(), 1 + 16);
+        assert!(
+            chunks.loaded.contains_key(&999),
+            "old chunk dropped before cover"
+        );
+
+        frame(&mut world, 0.0, &wanted);
+        frame(&mut world, 0.0, &wanted);
+        let chunks = world.resource::<ChunkSet<u32>>();
+        assert_eq!(chunks.loaded.len(), 40);
+        assert!(!chunks.loaded.contains_key(&999));
+    }
+
+    #[test]
+    fn rebuilt_chunks_move_to_the_new_layout() {
+        let mut world = world();
+        settle(&mut world, 0.0, &[3]);
+        world.resource_mut::<ChunkSet<u32>>().invalidate();
+        settle(&mut world, 5.0, &[3]);
+
+        let entity = world.resource::<ChunkSet<u32>>().loaded[&3].entity;
+        let t = world.get::<Transform>(entity).unwrap();
+        assert_eq!(t.translation, Vec3::X * 35.0);
+    }
+}
@@ -0,0 +1,251 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::ExportError;
+use crate::grid::Heightfield;
+use crate::height::HeightSource;
+use crate::params::TerrainParams;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeightmapFormat {
+    /// 16-bit grayscale, min..max stretched over 0..65535, range in tEXt chunks
+    Png16,
+    /// little-endian f32 meters with a `.json` sidecar
+    RawF32,
+    /// single `Y` channel of f32 meters
+    Exr,
+}
+
+impl HeightmapFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "png" => Some(Self::Png16),
+            "raw" | "r32" | "f32" => Some(Self::RawF32),
+            "exr" => Some(Self::Exr),
+            _ => None,
+        }
+    }
+}
+
+/// everything needed to turn the samples back into meters and world positions
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HeightmapMeta {
+    pub width: usize,
+    pub height: usize,
+    /// lowest / highest sample in meters
+    pub min: f32,
+    pub max: f32,
+    /// world xz of the first sample
+    pub origin: [f32; 2],
+    /// meters between samples
+    pub spacing: f32,
+    /// sample layout, e.g. `"f32le"`
+    pub sample: String,
+}
+
+/// bakes `height` over the params footprint at `res + 1` samples per side
+pub fn bake_heightmap(
+    p: TerrainParams,
+    height: &dyn HeightSource,
+    path: impl AsRef<Path>,
+    format: HeightmapFormat,
+) -> Result<HeightmapMeta, ExportError> {
+    export_heightmap(&Heightfield::from_params(p, height), path, format)
+}
+
+pub fn export_heightmap(
+    field: &Heightfield,
+    path: impl AsRef<Path>,
+    format: HeightmapFormat,
+) -> Result<HeightmapMeta, ExportError> {
+    let path = path.as_ref();
+    let (min, max) = field.min_max();
+    let mut meta = HeightmapMeta {
+        width: field.nx,
+        height: field.nz,
+        min,
+        max,
+        origin: field.origin.to_array(),
+        spacing: field.spacing,
+        sample: String::new(),
+    };
+
+    match format {
+        HeightmapFormat::Png16 => {
+            meta.sample = "u16be".into();
+            write_png16(field, &meta, path)?;
+        }
+        HeightmapFormat::RawF32 => {
+            meta.sample = "f32le".into();
+            write_raw_f32(field, path)?;
+            let sidecar = File::create(path.with_extension("json"))?;
+            serde_json::to_writer_pretty(BufWriter::new(sidecar), &meta)?;
+        }
+        HeightmapFormat::Exr => {
+            meta.sample = "f32".into();
+            write_exr(field, path)?;
+        }
+    }
+    Ok(meta)
+}
+
+fn write_png16(field: &Heightfield, meta: &HeightmapMeta, path: &Path) -> Result<(), ExportError> {
+    let range = meta.max - meta.min;
+    let mut bytes = Vec::with_capacity(field.data.len() * 2);
+    for &h in &field.data {
+        let t = if range > 0.0 {
+            (h - meta.min) / range
+        } else {
+            0.0
+        };
+        let v = (t.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16;
+        bytes.extend_from_slice(&v.to_be_bytes());
+    }
+
+    let w = BufWriter::new(File::create(path)?);
+    let mut enc = png::Encoder::new(w, field.nx as u32, field.nz as u32);
+    enc.set_color(png::ColorType::Grayscale);
+    enc.set_depth(png::BitDepth::Sixteen);
+    enc.add_text_chunk("europa.min".into(), meta.min.to_string())?;
+    enc.add_text_chunk("europa.max".into(), meta.max.to_string())?;
+    enc.add_text_chunk("europa.spacing".into(), meta.spacing.to_string())?;
+    enc.add_text_chunk(
+        "europa.origin".into(),
+        format!("{} {}", meta.origin[0], meta.origin[1]),
+    )?;
+    enc.write_header()?.write_image_data(&bytes)?;
+    Ok(())
+}
+
+fn write_raw_f32(field: &Heightfield, path: &Path) -> Result<(), ExportError> {
+    let mut w = BufWriter::new(File::create(path)?);
+    for &h in &field.data {
+        w.write_all(&h.to_le_bytes())?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+fn write_exr(field: &Heightfield, path: &Path) -> Result<(), ExportError> {
+    use exr::prelude::{Image, SpecificChannels, WritableImage};
+
+    let channels = SpecificChannels::build()
+        .with_channel("Y")
+        .with_pixel_fn(|pos| (field.get(pos.x(), pos.y()),));
+    Image::from_channels((field.nx, field.nz), channels)
+        .write()
+        .to_file(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use bevy::math::Vec2;
+
+    use super::*;
+
+    /// 4x3 ramp whose samples are distinct along both axes
+    fn field() -> Heightfield {
+        Heightfield {
+            nx: 4,
+            nz: 3,
+            origin: Vec2::new(-30.0, 12.0),
+            spacing: 2.5,
+            data: (0..12).map(|k| k as f32 * 1.5 - 4.0).collect(),
+        }
+    }
+
+    #[test]
+    fn png16_stretches_the_range_over_u16() {
+        let path = std::env::temp_dir().join("europa_heightmap.png");
+        let meta = export_heightmap(&field(), &path, HeightmapFormat::Png16).unwrap();
+        assert_eq!((meta.min, meta.max), (-4.0, 12.5));
+
+        let mut reader = png::Decoder::new(BufReader::new(File::open(&path).unwrap()))
+            .read_info()
+            .unwrap();
+        let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+        let frame = reader.next_frame(&mut buf).unwrap();
+        assert_eq!((frame.width, frame.height), (4, 3));
+        assert_eq!(frame.bit_depth, png::BitDepth::Sixteen);
+        let text = |key: &str| {
+            reader
+                .info()
+                .uncompressed_latin1_text
+                .iter()
+                .find(|c| c.keyword == key)
+                .map(|c| c.text.clone())
+        };
+        assert_eq!(text("europa.min").as_deref(), Some("-4"));
+        assert_eq!(text("europa.max").as_deref(), Some("12.5"));
+        assert_eq!(text("europa.origin").as_deref(), Some("-30 12"));
+        std::fs::remove_file(&path).ok();
+
+        let v: Vec<u16> = buf[..frame.buffer_size()]
+            .chunks_exact(2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .collect();
+        assert_eq!((v[0], v[11]), (0, u16::MAX));
+        for (&raw, &h) in v.iter().zip(&field().data) {
+            let back = meta.min + raw as f32 / u16::MAX as f32 * (meta.max - meta.min);
+            assert!((back - h).abs() < 1e-3, "{back} vs {h}");
+        }
+    }
+
+    #[test]
+    fn raw_is_little_endian_f32_with_a_sidecar() {
+        let path = std::env::temp_dir().join("europa_heightmap.raw");
+        export_heightmap(&field(), &path, HeightmapFormat::RawF32).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        let sidecar = std::fs::read_to_string(path.with_extension("json")).unwrap();
+        std::fs::remove_file(&path).ok();
+        std::fs::remove_file(path.with_extension("json")).ok();
+
+        assert_eq!(bytes.len(), 4 * 3 * 4);
+        let back: Vec<f32> = bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        assert_eq!(back, field().data);
+
+        let meta: HeightmapMeta = serde_json::from_str(&sidecar).unwrap();
+        assert_eq!((meta.width, meta.height), (4, 3));
+        assert_eq!(meta.sample, "f32le");
+        assert_eq!(meta.origin, [-30.0, 12.0]);
+        assert_eq!(meta.spacing, 2.5);
+    }
+
+    #[test]
+    fn exr_keeps_meters_in_y() {
+        use exr::prelude::{FlatSamples, read_first_flat_layer_from_file};
+
+        let path = std::env::temp_dir().join("europa_heightmap.exr");
+        export_heightmap(&field(), &path, HeightmapFormat::Exr).unwrap();
+        let image = read_first_flat_layer_from_file(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        let layer = &image.layer_data;
+        assert_eq!((layer.size.width(), layer.size.height()), (4, 3));
+        let channel = &layer.channel_data.list[0];
+        assert_eq!(channel.name.to_string(), "Y");
+        let FlatSamples::F32(samples) = &channel.sample_data else {
+            panic!("expected f32 samples");
+        };
+        assert_eq!(samples, &field().data);
+    }
+
+    #[test]
+    fn format_follows_the_extension() {
+        let f = |p: &str| HeightmapFormat::from_path(Path::new(p));
+        assert_eq!(f("a.PNG"), Some(HeightmapFormat::Png16));
+        assert_eq!(f("a.r32"), Some(HeightmapFormat::RawF32));
+        assert_eq!(f("a.exr"), Some(HeightmapFormat::Exr));
+        assert_eq!(f("a.tif"), None);
+    }
+}
@@ -0,0 +1,345 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use bevy::mesh::{PrimitiveTopology, VertexAttributeValues};
+use bevy::prelude::*;
+use serde_json::json;
+
+use super::ExportError;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MeshFormat {
+    /// binary glTF 2.0, y-up like bevy
+    Glb,
+    /// wavefront obj, y-up
+    Obj,
+    /// binary stl, rotated to z-up for slicers
+    Stl,
+}
+
+impl MeshFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "glb" => Some(Self::Glb),
+            "obj" => Some(Self::Obj),
+            "stl" => Some(Self::Stl),
+            _ => None,
+        }
+    }
+}
+
+/// flattened triangle list pulled out of a bevy `Mesh`
+struct Tris {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+}
+
+impl Tris {
+    fn from_mesh(mesh: &Mesh) -> Result<Self, ExportError> {
+        if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+            return Err(ExportError::Mesh(
+                "only triangle lists can be exported".into(),
+            ));
+        }
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            return Err(ExportError::Mesh("mesh has no f32x3 positions".into()));
+        };
+        let normals = match mesh.attribute(Mesh::ATTRIBUTE_NORMAL) {
+            Some(VertexAttributeValues::Float32x3(n)) => n.clone(),
+            _ => vec![[0.0, 1.0, 0.0]; positions.len()],
+        };
+        let uvs = match mesh.attribute(Mesh::ATTRIBUTE_UV_0) {
+            Some(VertexAttributeValues::Float32x2(uv)) => uv.clone(),
+            _ => vec![[0.0, 0.0]; positions.len()],
+        };
+        let indices = match mesh.indices() {
+            Some(ix) => ix.iter().map(|i| i as u32).collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+
+        Ok(Self {
+            positions: positions.clone(),
+            normals,
+            uvs,
+            indices,
+        })
+    }
+}
+
+pub fn export_mesh(
+    mesh: &Mesh,
+    path: impl AsRef<Path>,
+    format: MeshFormat,
+) -> Result<(), ExportError> {
+    let tris = Tris::from_mesh(mesh)?;
+    let mut w = BufWriter::new(File::create(path)?);
+    match format {
+        MeshFormat::Glb => write_glb(&tris, &mut w)?,
+        MeshFormat::Obj => write_obj(&tris, &mut w)?,
+        MeshFormat::Stl => write_stl(&tris, &mut w)?,
+    }
+    w.flush()?;
+    Ok(())
+}
+
+fn write_glb(t: &Tris, w: &mut impl Write) -> Result<(), ExportError> {
+    let n = t.positions.len();
+    let mut bin = Vec::with_capacity(n * 32 + t.indices.len() * 4);
+    for v in t.positions.iter().chain(&t.normals) {
+        bin.extend(v.iter().flat_map(|c| c.to_le_bytes()));
+    }
+    for uv in &t.uvs {
+        bin.extend(uv.iter().flat_map(|c| c.to_le_bytes()));
+    }
+    bin.extend(t.indices.iter().flat_map(|i| i.to_le_bytes()));
+
+    // POSITION accessors must carry bounds
+    let (min, max) = t.positions.iter().fold(
+        ([f32::INFINITY; 3], [f32::NEG_INFINITY; 3]),
+        |(mut lo, mut hi), p| {
+            for k in 0..3 {
+                lo[k] = lo[k].min(p[k]);
+                hi[k] = hi[k].max(p[k]);
+            }
+            (lo, hi)
+        },
+    );
+
+    let (pos_len, nrm_len, uv_len) = (n * 12, n * 12, n * 8);
+    let idx_len = t.indices.len() * 4;
+    const ARRAY_BUFFER: u32 = 34962;
+    const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+    const FLOAT: u32 = 5126;
+    const UNSIGNED_INT: u32 = 5125;
+
+    let doc = json!({
+        "asset": { "version": "2.0", "generator": "europa_terrain" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [{ "mesh": 0, "name": "Europa Terrain" }],
+        "meshes": [{
+            "name": "Europa Terrain",
+            "primitives": [{
+                "attributes": { "POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2 },
+                "indices": 3,
+                "mode": 4,
+            }],
+        }],
+        "buffers": [{ "byteLength": bin.len() }],
+        "bufferViews": [
+            { "buffer": 0, "byteOffset": 0, "byteLength": pos_len, "target": ARRAY_BUFFER },
+            { "buffer": 0, "byteOffset": pos_len, "byteLength": nrm_len, "target": ARRAY_BUFFER },
+            { "buffer": 0, "byteOffset": pos_len + nrm_len, "byteLength": uv_len, "target": ARRAY_BUFFER },
+            { "buffer": 0, "byteOffset": pos_len + nrm_len + uv_len, "byteLength": idx_len, "target": ELEMENT_ARRAY_BUFFER },
+        ],
+        "accessors": [
+            { "bufferView": 0, "componentType": FLOAT, "count": n, "type": "VEC3", "min": min, "max": max },
+            { "bufferView": 1, "componentType": FLOAT, "count": n, "type": "VEC3" },
+            { "bufferView": 2, "componentType": FLOAT, "count": n, "type": "VEC2" },
+            { "bufferView": 3, "componentType": UNSIGNED_INT, "count": t.indices.len(), "type": "SCALAR" },
+        ],
+    });
+
+    // chunks are 4-byte aligned: json pads with spaces, bin with zeros
+    let mut json = serde_json::to_vec(&doc)?;
+    json.resize(json.len().next_multiple_of(4), b' ');
+    bin.resize(bin.len().next_multiple_of(4), 0);
+
+    let total = 12 + 8 + json.len() + 8 + bin.len();
+    w.write_all(b"glTF")?;
+    w.write_all(&2u32.to_le_bytes())?;
+    w.write_all(&(total as u32).to_le_bytes())?;
+    w.write_all(&(json.len() as u32).to_le_bytes())?;
+    w.write_all(b"JSON")?;
+    w.write_all(&json)?;
+    w.write_all(&(bin.len() as u32).to_le_bytes())?;
+    w.write_all(b"BIN\0")?;
+    w.write_all(&bin)?;
+    Ok(())
+}
+
+fn write_obj(t: &Tris, w: &mut impl Write) -> Result<(), ExportError> {
+    writeln!(w, "# europa_terrain")?;
+    writeln!(w, "o EuropaTerrain")?;
+    for [x, y, z] in &t.positions {
+        writeln!(w, "v {x} {y} {z}")?;
+    }
+    for [u, v] in &t.uvs {
+        // obj puts the uv origin bottom-left
+        writeln!(w, "vt {u} {}", 1.0 - v)?;
+    }
+    for [x, y, z] in &t.normals {
+        writeln!(w, "vn {x} {y} {z}")?;
+    }
+    for tri in t.indices.chunks_exact(3) {
+        let [a, b, c] = [tri[0] + 1, tri[1] + 1, tri[2] + 1];
+        writeln!(w, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+    }
+    Ok(())
+}
+
+fn write_stl(t: &Tris, w: &mut impl Write) -> Result<(), ExportError> {
+    // y-up -> z-up, a +90° turn about x keeps the winding
+    let zup = |p: [f32; 3]| Vec3::new(p[0], -p[2], p[1]);
+
+    let mut header = [0u8; 80];
+    let tag = b"europa_terrain binary stl";
+    header[..tag.len()].copy_from_slice(tag);
+    w.write_all(&header)?;
+    w.write_all(&((t.indices.len() / 3) as u32).to_le_bytes())?;
+
+    for tri in t.indices.chunks_exact(3) {
+        let [a, b, c] = [tri[0], tri[1], tri[2]].map(|i| zup(t.positions[i as usize]));
+        let n = (b - a).cross(c - a).normalize_or_zero();
+        for v in [n, a, b, c] {
+            for k in v.to_array() {
+                w.write_all(&k.to_le_bytes())?;
+            }
+        }
+        w.write_all(&0u16.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::DVec2;
+
+    use super::*;
+    use crate::height::HeightSource;
+    use crate::{TerrainParams, build_europa_mesh};
+
+    struct Slope;
+
+    impl HeightSource for Slope {
+        fn height_at_world(&self, p: DVec2) -> f32 {
+            (0.25 * p.x) as f32
+        }
+    }
+
+    /// 4x4 vertices with only the first two rows of quads indexed: 12 triangles
+    fn grid() -> Mesh {
+        let p = TerrainParams {
+            size: 6.0,
+            res: 3,
+            curvature: None,
+            ..TerrainParams::europa_demo()
+        };
+        let mut mesh = build_europa_mesh(p, &Slope);
+        let Some(ix) = mesh.indices() else {
+            panic!("grid mesh is indexed");
+        };
+        let kept: Vec<u32> = ix.iter().map(|i| i as u32).take(3 * 2 * 6).collect();
+        mesh.insert_indices(bevy::mesh::Indices::U32(kept));
+        mesh
+    }
+
+    fn export(format: MeshFormat, name: &str) -> Vec<u8> {
+        let path = std::env::temp_dir().join(name);
+        export_mesh(&grid(), &path, format).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        bytes
+    }
+
+    #[test]
+    fn obj_faces_point_up() {
+        let text = String::from_utf8(export(MeshFormat::Obj, "europa_mesh.obj")).unwrap();
+        let verts: Vec<Vec3> = text
+            .lines()
+            .filter_map(|l| l.strip_prefix("v "))
+            .map(|l| {
+                let c: Vec<f32> = l.split(' ').map(|c| c.parse().unwrap()).collect();
+                Vec3::new(c[0], c[1], c[2])
+            })
+            .collect();
+        let faces: Vec<[usize; 3]> = text
+            .lines()
+            .filter_map(|l| l.strip_prefix("f "))
+            .map(|l| {
+                let mut it = l.split(' ').map(|v| {
+                    let (i, _) = v.split_once('/').unwrap();
+                    i.parse::<usize>().unwrap() - 1
+                });
+                [(); 3].map(|_| it.next().unwrap())
+            })
+            .collect();
+
+        assert_eq!(verts.len(), 16);
+        assert_eq!(faces.len(), 12);
+        assert_eq!(text.lines().filter(|l| l.starts_with("vt ")).count(), 16);
+        assert_eq!(text.lines().filter(|l| l.starts_with("vn ")).count(), 16);
+        for [a, b, c] in faces {
+            let n = (verts[b] - verts[a]).cross(verts[c] - verts[a]);
+            assert!(n.y > 0.0, "face {a} {b} {c} winds downward");
+        }
+    }
+
+    #[test]
+    fn stl_is_z_up_and_counted() {
+        let bytes = export(MeshFormat::Stl, "europa_mesh.stl");
+        let count = u32::from_le_bytes(bytes[80..84].try_into().unwrap()) as usize;
+        assert_eq!(count, 12);
+        assert_eq!(bytes.len(), 84 + 50 * count);
+
+        let f = |at: usize| f32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        for t in 0..count {
+            let at = 84 + 50 * t;
+            let v = |k: usize| Vec3::new(f(at + 12 * k), f(at + 12 * k + 4), f(at + 12 * k + 8));
+            let (n, a, b, c) = (v(0), v(1), v(2), v(3));
+            assert!(n.z > 0.9, "{n}");
+            // stored normal agrees with the winding
+            assert!((b - a).cross(c - a).normalize().dot(n) > 0.999);
+        }
+    }
+
+    #[test]
+    fn glb_parses_back() {
+        let bytes = export(MeshFormat::Glb, "europa_mesh.glb");
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        assert_eq!(&bytes[..4], b"glTF");
+        assert_eq!(u32_at(4), 2);
+        assert_eq!(u32_at(8) as usize, bytes.len());
+
+        let json_len = u32_at(12) as usize;
+        assert_eq!(&bytes[16..20], b"JSON");
+        let doc: serde_json::Value = serde_json::from_slice(&bytes[20..20 + json_len]).unwrap();
+        let bin_at = 20 + json_len;
+        let bin_len = u32_at(bin_at) as usize;
+        assert_eq!(&bytes[bin_at + 4..bin_at + 8], b"BIN\0");
+        assert_eq!(bin_at + 8 + bin_len, bytes.len());
+        assert!(doc["buffers"][0]["byteLength"].as_u64().unwrap() as usize <= bin_len);
+
+        let accessors = doc["accessors"].as_array().unwrap();
+        let counts: Vec<u64> = accessors
+            .iter()
+            .map(|a| a["count"].as_u64().unwrap())
+            .collect();
+        assert_eq!(counts, [16, 16, 16, 36]);
+        assert_eq!(accessors[0]["min"][0], -3.0);
+        assert_eq!(accessors[0]["max"][0], 3.0);
+
+        // indices come back in range and in order
+        let views = &doc["bufferViews"];
+        let idx_at = bin_at + 8 + views[3]["byteOffset"].as_u64().unwrap() as usize;
+        let indices: Vec<u32> = (0..36).map(|k| u32_at(idx_at + 4 * k)).collect();
+        let expected: Vec<u32> = grid().indices().unwrap().iter().map(|i| i as u32).collect();
+        assert_eq!(indices, expected);
+    }
+
+    #[test]
+    fn rejects_non_triangle_lists() {
+        let mesh = Mesh::new(PrimitiveTopology::LineList, default());
+        let path = std::env::temp_dir().join("europa_mesh_lines.obj");
+        assert!(matches!(
+            export_mesh(&mesh, &path, MeshFormat::Obj),
+            Err(ExportError::Mesh(_))
+        ));
+    }
+}
@@ -0,0 +1,20 @@
+//! writing terrain out for external tools
+
+use thiserror::Error;
+
+pub mod heightmap;
+pub mod mesh;
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("could not write export: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("png encoding failed: {0}")]
+    Png(#[from] png::EncodingError),
+    #[error("exr encoding failed: {0}")]
+    Exr(#[from] exr::error::Error),
+    #[error("json encoding failed: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("cannot export mesh: {0}")]
+    Mesh(String),
+}
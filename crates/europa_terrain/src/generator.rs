@@ -0,0 +1,64 @@
+use bevy::prelude::*;
+
+use crate::height::noise::{Fractal, Perlin, PerlinFbm, PerlinRidged};
+use crate::height::{HeightFn, arc, comb, warp};
+use crate::params::TerrainParams;
+
+/// builds a height graph from the params alone, rerun whenever they change
+pub type HeightGenerator = fn(&TerrainParams) -> HeightFn;
+
+/// default Europa surface: fbm plains plus ridges stretched along `line_dir`,
+/// domain warped, then scaled to `amp` meters
+pub fn europa_height(p: &TerrainParams) -> HeightFn {
+    let base = PerlinFbm {
+        noise: Perlin::new(p.seed),
+        fractal: Fractal {
+            freq: p.freq,
+            octaves: 5,
+            lacunarity: 2.0,
+            gain: 0.5,
+            amplitude: 1.0,
+        },
+    };
+
+    // anisotropic ridges aligned by an oriented wrapper
+    let ridged = PerlinRidged {
+        noise: Perlin::new(p.seed ^ 0xB529_7A4D),
+        fractal: Fractal {
+            freq: p.freq * 2.5,
+            octaves: 4,
+            lacunarity: 2.2,
+            gain: 0.75,
+            amplitude: 1.0,
+        },
+        z_anisotropy: 2.0,
+    };
+
+    let oriented = warp::Oriented {
+        source: ridged,
+        dir: p.line_dir.normalize_or(Vec2::X),
+        main_scale: 1.0,
+        ortho_scale: 0.35,
+    };
+
+    let warp = warp::Warp2D {
+        source: comb::Add2 {
+            a: base,
+            b: oriented,
+        },
+        perlin: Perlin::new(p.seed ^ 0x9E37_79B9),
+        warp_amp: 40.0,
+        warp_freq: p.freq * 0.6,
+        octaves: 3,
+        lacunarity: 2.1,
+        gain: 0.55,
+    };
+
+    arc(comb::Bias {
+        s: comb::Scale {
+            s: warp,
+            scale: p.amp,
+        },
+        bias: -0.1 * p.amp,
+    })
+}
@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use bevy::math::DVec3;
+use noise::NoiseFn;
+
+use crate::height::noise::Fractal;
+
+/// 3D counterpart of `HeightSource`: height above the reference sphere for a
+/// unit direction from the globe centre. sampling by direction has no seams or
+/// pole pinching, whichever cube face asks
+pub trait SphereHeightSource: Send + Sync + 'static {
+    fn height_at_dir(&self, dir: DVec3) -> f32;
+}
+
+pub type SphereHeightFn = Arc<dyn SphereHeightSource>;
+
+pub fn sphere_arc<S: SphereHeightSource>(s: S) -> SphereHeightFn {
+    Arc::new(s)
+}
+
+impl SphereHeightSource for SphereHeightFn {
+    fn height_at_dir(&self, dir: DVec3) -> f32 {
+        self.as_ref().height_at_dir(dir)
+    }
+}
+
+/// fbm of 3D perlin over the sphere surface. `fractal.freq` is per meter of
+/// surface, so sizes match the flat sources
+pub struct SphereFbm {
+    pub perlin: noise::Perlin,
+    pub radius: f32,
+    pub fractal: Fractal,
+}
+
+impl SphereHeightSource for SphereFbm {
+    fn height_at_dir(&self, dir: DVec3) -> f32 {
+        let fr = &self.fractal;
+        // f64 all the way: dir * radius is megameters on Europa
+        let mut p = dir * (self.radius as f64 * fr.freq as f64);
+        let mut a = 1.0;
+        let mut sum = 0.0;
+        let mut amp = 0.0;
+        for _ in 0..fr.octaves {
+            sum += a * self.perlin.get(p.to_array());
+            amp += a;
+            p *= fr.lacunarity as f64;
+            a *= fr.gain as f64;
+        }
+        (sum / amp) as f32 * fr.amplitude
+    }
+}
+
+/// ridged 3D perlin over the sphere, 0..`amplitude` like `Ridged`
+pub struct SphereRidged {
+    pub perlin: noise::Perlin,
+    pub radius: f32,
+    pub fractal: Fractal,
+}
+
+impl SphereHeightSource for SphereRidged {
+    fn height_at_dir(&self, dir: DVec3) -> f32 {
+        let fr = &self.fractal;
+        let mut p = dir * (self.radius as f64 * fr.freq as f64);
+        let mut a = 1.0;
+        let mut sum = 0.0;
+        let mut amp = 0.0;
+        for _ in 0..fr.octaves {
+            let v = 1.0 - self.perlin.get(p.to_array()).abs();
+            sum += a * v * v;
+            amp += a;
+            p *= fr.lacunarity as f64;
+            a *= fr.gain as f64;
+        }
+        (sum / amp).clamp(0.0, 1.0) as f32 * fr.amplitude
+    }
+}
+
+pub struct SphereSum {
+    pub items: Vec<SphereHeightFn>,
+}
+
+impl SphereHeightSource for SphereSum {
+    fn height_at_dir(&self, dir: DVec3) -> f32 {
+        self.items.iter().map(|s| s.height_at_dir(dir)).sum()
+    }
+}
+
+/// whole-moon surface: broad undulation, ridge belts at tens of km and the
+/// same meter-scale roughness the flat patch has
+pub fn europa_globe_height(radius: f32, seed: u32) -> SphereHeightFn {
+    let regional = SphereFbm {
+        perlin: noise::Perlin::new(seed),
+        radius,
+        fractal: Fractal {
+            freq: 1.0 / 400_000.0,
+            octaves: 6,
+            lacunarity: 2.0,
+            gain: 0.5,
+            amplitude: 400.0,
+        },
+    };
+    let ridges = SphereRidged {
+        perlin: noise::Perlin::new(seed ^ 0xB529_7A4D),
+        radius,
+        fractal: Fractal {
+            freq: 1.0 / 20_000.0,
+            octaves: 6,
+            lacunarity: 2.2,
+            gain: 0.6,
+            amplitude: 120.0,
+        },
+    };
+    let detail = SphereFbm {
+        perlin: noise::Perlin::new(seed ^ 0x9E37_79B9),
+        radius,
+        fractal: Fractal {
+            freq: 1.0 / 600.0,
+            octaves: 5,
+            lacunarity: 2.0,
+            gain: 0.5,
+            amplitude: 12.0,
+        },
+    };
+    sphere_arc(SphereSum {
+        items: vec![sphere_arc(regional), sphere_arc(ridges), sphere_arc(detail)],
+    })
+}
@@ -0,0 +1,185 @@
+use bevy::math::{DVec2, DVec3};
+use bevy::prelude::*;
+
+use crate::chunks::ChunkBudget;
+
+/// quadtree streaming settings for the cube-sphere
+#[derive(Resource, Clone, Copy)]
+pub struct GlobeLod {
+    /// quads per chunk side, the same at every depth
+    pub chunk_res: u32,
+    /// deepest level; each face is 2^depth chunks across
+    pub max_depth: u8,
+    /// a node splits while the camera is closer than `edge * split_factor`
+    pub split_factor: f32,
+    /// full-resolution chunk builds started per frame
+    pub builds_per_frame: usize,
+    /// placeholder meshes built per frame, on the spot like the flat ones
+    pub placeholders_per_frame: usize,
+    /// cap on chunk builds running in the background at once
+    pub max_in_flight: usize,
+    /// quads per side of the placeholder shown while a chunk builds
+    pub placeholder_res: u32,
+    /// skirt depth as a fraction of chunk edge, 0 disables skirts
+    pub skirt_ratio: f32,
+}
+
+impl Default for GlobeLod {
+    fn default() -> Self {
+        // a face is ~2450 km across on Europa, depth 15 gets chunks to ~75 m
+        Self {
+            chunk_res: 32,
+            max_depth: 15,
+            split_factor: 1.5,
+            builds_per_frame: 6,
+            placeholders_per_frame: 24,
+            max_in_flight: 24,
+            placeholder_res: 4,
+            skirt_ratio: 0.01,
+        }
+    }
+}
+
+impl GlobeLod {
+    pub(crate) fn budget(&self) -> ChunkBudget {
+        ChunkBudget {
+            placeholders_per_frame: self.placeholders_per_frame,
+            builds_per_frame: self.builds_per_frame,
+            max_in_flight: self.max_in_flight,
+        }
+    }
+}
+
+/// face axes `(normal, u, v)`, picked so `v × u = normal` and a grid laid out
+/// along u then v winds like the flat patches
+const FACES: [[DVec3; 3]; 6] = [
+    [DVec3::X, DVec3::Z, DVec3::Y],
+    [DVec3::NEG_X, DVec3::Z, DVec3::NEG_Y],
+    [DVec3::Y, DVec3::X, DVec3::Z],
+    [DVec3::NEG_Y, DVec3::X, DVec3::NEG_Z],
+    [DVec3::Z, DVec3::Y, DVec3::X],
+    [DVec3::NEG_Z, DVec3::Y, DVec3::NEG_X],
+];
+
+/// unit direction for face coords `uv` in -1..1. the cube is spherified
+/// rather than normalized so cells keep roughly equal area out to the corners.
+/// f64 throughout: at Europa's radius an f32 direction is already a decimeter off
+pub fn cube_to_sphere(face: u8, uv: DVec2) -> DVec3 {
+    let [n, u, v] = FACES[face as usize];
+    let p = n + u * uv.x + v * uv.y;
+    let sq = p * p;
+    DVec3::new(
+        p.x * (1.0 - sq.y / 2.0 - sq.z / 2.0 + sq.y * sq.z / 3.0).sqrt(),
+        p.y * (1.0 - sq.z / 2.0 - sq.x / 2.0 + sq.z * sq.x / 3.0).sqrt(),
+        p.z * (1.0 - sq.x / 2.0 - sq.y / 2.0 + sq.x * sq.y / 3.0).sqrt(),
+    )
+}
+
+/// address of a quadtree node on one cube face
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub(crate) struct GlobeChunkKey {
+    pub face: u8,
+    pub depth: u8,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl GlobeChunkKey {
+    /// edge length in face coords
+    pub fn uv_size(&self) -> f64 {
+        2.0 / (1u32 << self.depth) as f64
+    }
+
+    /// face coords of the min corner
+    pub fn uv_origin(&self) -> DVec2 {
+        let s = self.uv_size();
+        DVec2::new(-1.0 + self.x as f64 * s, -1.0 + self.y as f64 * s)
+    }
+
+    pub fn center_dir(&self) -> DVec3 {
+        cube_to_sphere(
+            self.face,
+            self.uv_origin() + DVec2::splat(self.uv_size() * 0.5),
+        )
+    }
+
+    /// the centre on the reference sphere, relative to the globe centre. chunk
+    /// mesh positions are relative to it
+    pub fn anchor(&self, radius: f32) -> DVec3 {
+        self.center_dir() * radius as f64
+    }
+
+    /// rough surface edge length, meters
+    pub fn edge(&self, radius: f32) -> f32 {
+        radius * std::f32::consts::FRAC_PI_2 / (1u32 << self.depth) as f32
+    }
+
+    fn children(&self) -> [GlobeChunkKey; 4] {
+        let (depth, x, y) = (self.depth + 1, self.x * 2, self.y * 2);
+        let child = |dx, dy| GlobeChunkKey {
+            face: self.face,
+            depth,
+            x: x + dx,
+            y: y + dy,
+        };
+        [child(0, 0), child(1, 0), child(0, 1), child(1, 1)]
+    }
+
+    /// distance from `eye` (relative to the globe centre) to roughly the nearest point of the chunk
+    pub fn distance(&self, radius: f32, eye: DVec3) -> f32 {
+        let centre = self.anchor(radius);
+        ((eye - centre).length() as f32 - 0.7 * self.edge(radius)).max(0.0)
+    }
+}
+
+/// leaf chunks of the six face quadtrees around `eye`, relative to the globe centre
+pub(crate) fn select_globe_chunks(lod: &GlobeLod, radius: f32, eye: DVec3) -> Vec<GlobeChunkKey> {
+    let mut leaves = Vec::new();
+    let mut stack: Vec<GlobeChunkKey> = (0..6)
+        .map(|face| GlobeChunkKey {
+            face,
+            depth: 0,
+            x: 0,
+            y: 0,
+        })
+        .collect();
+    while let Some(key) = stack.pop() {
+        let near = key.distance(radius, eye) < key.edge(radius) * lod.split_factor;
+        if key.depth < lod.max_depth && near {
+            stack.extend(key.children());
+        } else {
+            leaves.push(key);
+        }
+    }
+    leaves
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn faces_meet_and_wind_outward() {
+        for face in 0..6 {
+            let [n, u, v] = FACES[face as usize];
+            assert_eq!(v.cross(u), n);
+            let p = cube_to_sphere(face, DVec2::new(0.3, -0.6));
+            assert!((p.length() - 1.0).abs() < 1e-5);
+        }
+        // +X face's top edge is the +Y face's right edge
+        let a = cube_to_sphere(0, DVec2::new(0.25, 1.0));
+        let b = cube_to_sphere(2, DVec2::new(1.0, 0.25));
+        assert!(a.distance(b) < 1e-12);
+    }
+
+    #[test]
+    fn refines_under_the_camera_only() {
+        let lod = GlobeLod::default();
+        let r = 1_560_800.0;
+        let leaves = select_globe_chunks(&lod, r, DVec3::Y * (r as f64 + 100.0));
+        let deepest = leaves.iter().max_by_key(|k| k.depth).unwrap();
+        assert_eq!(deepest.depth, lod.max_depth);
+        assert!(deepest.center_dir().y > 0.99);
+        assert!(leaves.len() < 1000, "{}", leaves.len());
+    }
+}
@@ -0,0 +1,190 @@
+use bevy::math::{DVec2, DVec3};
+use bevy::mesh::{Indices, PrimitiveTopology};
+use bevy::prelude::*;
+
+use super::height::SphereHeightSource;
+use super::lod::{GlobeChunkKey, cube_to_sphere};
+use crate::mesh::add_skirts;
+
+/// chunk with `n` quads per side. face coords, directions and points are
+/// worked out in f64 and only the offsets from `anchor`, the chunk centre on
+/// the reference sphere, are stored as f32, so the rounding scales with the
+/// chunk rather than the radius (micrometers on the deepest ones). gives up with `None` once `cancelled` turns true
+pub(crate) fn build_globe_chunk_mesh(
+    key: GlobeChunkKey,
+    radius: f32,
+    n: u32,
+    skirt: f32,
+    height: &dyn SphereHeightSource,
+    cancelled: &(dyn Fn() -> bool + Sync),
+) -> Option<Mesh> {
+    let anchor = key.anchor(radius);
+    let v = n as usize + 1;
+    // one extra ring all round so border normals see past the edge
+    let w = v + 2;
+    let step = key.uv_size() / n as f64;
+    let origin = key.uv_origin() - DVec2::splat(step);
+
+    let mut dirs = Vec::with_capacity(w * w);
+    let mut points = Vec::with_capacity(w * w);
+    for j in 0..w {
+        if cancelled() {
+            return None;
+        }
+        for i in 0..w {
+            let uv = origin + DVec2::new(i as f64, j as f64) * step;
+            // past a face edge the ring runs onto the extended cube, close enough for normals
+            let dir = cube_to_sphere(key.face, uv).normalize();
+            let r = radius as f64 + height.height_at_dir(dir) as f64;
+            dirs.push(dir.as_vec3());
+            points.push(dir * r - anchor);
+        }
+    }
+
+    let mut positions = Vec::with_capacity(v * v);
+    let mut normals = Vec::with_capacity(v * v);
+    let mut uvs = Vec::with_capacity(v * v);
+    let at = |i: usize, j: usize| points[j * w + i];
+    for j in 1..=v {
+        for i in 1..=v {
+            let du: DVec3 = at(i + 1, j) - at(i - 1, j);
+            let dv: DVec3 = at(i, j + 1) - at(i, j - 1);
+            let dir = dirs[j * w + i];
+            let mut normal = dv.cross(du).as_vec3().normalize();
+            if !normal.is_finite() || normal.dot(dir) <= 0.0 {
+                normal = dir;
+            }
+            positions.push(at(i, j).as_vec3().to_array());
+            normals.push(normal.to_array());
+            uvs.push([(i - 1) as f32 / n as f32, (j - 1) as f32 / n as f32]);
+        }
+    }
+
+    let mut indices = Vec::with_capacity((n * n * 6) as usize);
+    for j in 0..n {
+        for i in 0..n {
+            let i0 = j * (n + 1) + i;
+            let i1 = i0 + 1;
+            let i2 = i0 + (n + 1);
+            let i3 = i2 + 1;
+            indices.extend_from_slice(&[i0, i2, i1, i1, i2, i3]);
+        }
+    }
+
+    if skirt > 0.0 {
+        // straight down towards the centre
+        let lower = |p: [f32; 3]| {
+            let local = Vec3::from(p).as_dvec3();
+            let down = -(local + anchor).normalize() * skirt as f64;
+            (local + down).as_vec3().to_array()
+        };
+        add_skirts(
+            n,
+            &lower,
+            &mut positions,
+            &mut normals,
+            &mut uvs,
+            &mut indices,
+        );
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, default());
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.insert_indices(Indices::U32(indices));
+    Some(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::globe::europa_globe_height;
+    use bevy::mesh::VertexAttributeValues;
+
+    fn world_positions(key: GlobeChunkKey, radius: f32, n: u32) -> Vec<DVec3> {
+        let height = europa_globe_height(radius, 3);
+        let mesh = build_globe_chunk_mesh(key, radius, n, 0.0, height.as_ref(), &|| false).unwrap();
+        let Some(VertexAttributeValues::Float32x3(p)) = mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("no positions");
+        };
+        let anchor = key.anchor(radius);
+        p.iter()
+            .map(|&p| Vec3::from(p).as_dvec3() + anchor)
+            .collect()
+    }
+
+    #[test]
+    fn deep_chunks_are_smooth_on_a_bare_sphere() {
+        struct Bare;
+        impl SphereHeightSource for Bare {
+            fn height_at_dir(&self, _: DVec3) -> f32 {
+                0.0
+            }
+        }
+        let (radius, n) = (1_560_800.0, 8);
+        // a ~75 m chunk near a face corner, where the spherified grid is most skewed
+        let key = GlobeChunkKey {
+            face: 2,
+            depth: 15,
+            x: (1 << 15) - 3,
+            y: (1 << 15) - 7,
+        };
+        let mesh = build_globe_chunk_mesh(key, radius, n, 0.0, &Bare, &|| false).unwrap();
+        let (
+            Some(VertexAttributeValues::Float32x3(positions)),
+            Some(VertexAttributeValues::Float32x3(normals)),
+        ) = (
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION),
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL),
+        )
+        else {
+            panic!("no positions or normals");
+        };
+        let anchor = key.anchor(radius);
+        let (step, v) = (key.uv_size() / n as f64, n as usize + 1);
+        for (k, (&p, &normal)) in positions.iter().zip(normals).enumerate() {
+            let uv = key.uv_origin() + DVec2::new((k % v) as f64, (k / v) as f64) * step;
+            let want = cube_to_sphere(key.face, uv).normalize() * radius as f64;
+            let p = Vec3::from(p).as_dvec3() + anchor;
+            assert!(
+                p.distance(want) < 1e-3,
+                "vertex {k} off by {} m",
+                p.distance(want)
+            );
+            let angle = Vec3::from(normal).angle_between(want.normalize().as_vec3());
+            assert!(
+                angle.to_degrees() < 0.05,
+                "normal {k} off by {}°",
+                angle.to_degrees()
+            );
+        }
+    }
+
+    #[test]
+    fn chunks_meet_across_a_face_edge() {
+        let (radius, n) = (1_560_800.0, 8);
+        // top edge of +X, x in -1..0, is the right edge of +Y, z in -1..0
+        let side = GlobeChunkKey {
+            face: 0,
+            depth: 1,
+            x: 0,
+            y: 1,
+        };
+        let top = GlobeChunkKey {
+            face: 2,
+            depth: 1,
+            x: 1,
+            y: 0,
+        };
+        let a = world_positions(side, radius, n);
+        let b = world_positions(top, radius, n);
+        let v = n as usize + 1;
+        for k in 0..v {
+            let pa = a[(v - 1) * v + k];
+            let pb = b[k * v + (v - 1)];
+            assert!(pa.distance(pb) < 0.5, "seam gap {} m", pa.distance(pb));
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use bevy::ecs::system::SystemParam;
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy::transform::TransformSystems;
+
+use crate::origin::{RebaseSet, WorldOrigin};
+use crate::params::EUROPA_RADIUS;
+
+mod height;
+mod lod;
+mod mesh;
+mod systems;
+
+pub use height::{
+    SphereFbm, SphereHeightFn, SphereHeightSource, SphereRidged, SphereSum, europa_globe_height,
+    sphere_arc,
+};
+pub use lod::{GlobeLod, cube_to_sphere};
+
+/// where the globe sits in the world
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct Globe {
+    pub center: Vec3,
+    /// reference sphere, heights are measured from it
+    pub radius: f32,
+}
+
+impl Globe {
+    /// Europa with its north pole at the world origin, so "up" near the start
+    /// is +Y as on the flat patch
+    pub fn europa() -> Self {
+        Self {
+            center: Vec3::new(0.0, -EUROPA_RADIUS, 0.0),
+            radius: EUROPA_RADIUS,
+        }
+    }
+
+    /// local up (unit) at world point `p`, see `GlobeSampler::up` for render space
+    pub fn up(&self, p: Vec3) -> Vec3 {
+        (p - self.center).normalize_or(Vec3::Y)
+    }
+}
+
+#[derive(Clone)]
+pub struct GlobePlugin {
+    pub globe: Globe,
+    pub lod: GlobeLod,
+    pub height: SphereHeightFn,
+}
+
+impl GlobePlugin {
+    pub fn europa_default() -> Self {
+        let globe = Globe::europa();
+        Self {
+            globe,
+            lod: GlobeLod::default(),
+            height: europa_globe_height(globe.radius, 1337),
+        }
+    }
+}
+
+#[derive(Resource)]
+struct SphereHeightResource(pub SphereHeightFn);
+
+impl Plugin for GlobePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.globe)
+            .insert_resource(self.lod)
+            .insert_resource(SphereHeightResource(self.height.clone()))
+            .init_resource::<WorldOrigin>()
+            .add_systems(Startup, systems::spawn_globe)
+            .add_systems(Update, systems::stream_globe_chunks)
+            .add_systems(
+                PostUpdate,
+                systems::follow_origin
+                    .run_if(resource_changed::<WorldOrigin>)
+                    .after(RebaseSet)
+                    .before(TransformSystems::Propagate),
+            );
+    }
+}
+
+/// read access to the globe surface, the counterpart of `TerrainSampler`.
+/// points are in render space, relative to `WorldOrigin`
+#[derive(SystemParam)]
+pub struct GlobeSampler<'w> {
+    globe: Res<'w, Globe>,
+    height: Res<'w, SphereHeightResource>,
+    origin: Res<'w, WorldOrigin>,
+}
+
+impl GlobeSampler<'_> {
+    pub fn globe(&self) -> Globe {
+        *self.globe
+    }
+
+    /// height above the reference sphere along unit `dir` from the centre
+    pub fn height_at_dir(&self, dir: DVec3) -> f32 {
+        self.height.0.height_at_dir(dir)
+    }
+
+    /// local up (unit) at `p`
+    pub fn up(&self, p: Vec3) -> Vec3 {
+        self.centered(p).normalize_or(DVec3::Y).as_vec3()
+    }
+
+    /// the ground straight below (or above) `p`
+    pub fn ground_below(&self, p: Vec3) -> Vec3 {
+        let up = self.centered(p).normalize_or(DVec3::Y);
+        let r = self.globe.radius as f64 + self.height_at_dir(up) as f64;
+        self.origin.to_render(self.globe.center.as_dvec3() + up * r)
+    }
+
+    /// meters from `p` down to the ground, negative underground
+    pub fn altitude(&self, p: Vec3) -> f32 {
+        let rel = self.centered(p);
+        let up = rel.normalize_or(DVec3::Y);
+        let ground = self.globe.radius as f64 + self.height_at_dir(up) as f64;
+        (rel.length() - ground) as f32
+    }
+
+    /// world offset of render-space `p` from the globe centre
+    fn centered(&self, p: Vec3) -> DVec3 {
+        self.origin.to_world(p) - self.globe.center.as_dvec3()
+    }
+}
@@ -0,0 +1,110 @@
+use bevy::prelude::*;
+
+use super::lod::{GlobeChunkKey, GlobeLod, select_globe_chunks};
+use super::mesh::build_globe_chunk_mesh;
+use super::{Globe, SphereHeightFn, SphereHeightResource};
+use crate::chunks::{ChunkBuild, ChunkLayout, ChunkSet};
+use crate::origin::WorldOrigin;
+
+/// same lifecycle as the flat `TerrainChunks`
+pub(crate) type GlobeChunks = ChunkSet<GlobeChunkKey>;
+
+#[derive(Component)]
+pub(crate) struct GlobeChunk;
+
+pub(crate) fn spawn_globe(mut commands: Commands, mut mats: ResMut<Assets<StandardMaterial>>) {
+    let chunks = GlobeChunks::spawn(&mut commands, &mut mats, "Europa Globe");
+    commands.insert_resource(chunks);
+}
+
+/// the cube-sphere faces cut up by the current `GlobeLod`
+struct GlobeLayout<'a> {
+    lod: &'a GlobeLod,
+    globe: &'a Globe,
+    origin: &'a WorldOrigin,
+    height: &'a SphereHeightFn,
+}
+
+impl ChunkLayout for GlobeLayout<'_> {
+    type Key = GlobeChunkKey;
+
+    fn translation(&self, key: &GlobeChunkKey) -> Vec3 {
+        chunk_translation(key, self.globe, self.origin)
+    }
+
+    fn placeholder(&self, key: &GlobeChunkKey) -> Mesh {
+        let (lod, radius) = (self.lod, self.globe.radius);
+        let res = lod.placeholder_res.clamp(1, lod.chunk_res.max(1));
+        let skirt = key.edge(radius) * lod.skirt_ratio;
+        build_globe_chunk_mesh(*key, radius, res, skirt, self.height.as_ref(), &|| false)
+            .expect("build without cancellation always finishes")
+    }
+
+    fn build(&self, key: &GlobeChunkKey) -> ChunkBuild {
+        let source = self.height.clone();
+        let (key, radius, res) = (*key, self.globe.radius, self.lod.chunk_res);
+        let skirt = key.edge(radius) * self.lod.skirt_ratio;
+        Box::new(move |cancelled| {
+            build_globe_chunk_mesh(key, radius, res, skirt, source.as_ref(), cancelled)
+        })
+    }
+
+    fn tag(&self, key: &GlobeChunkKey) -> impl Bundle {
+        (
+            GlobeChunk,
+            Name::new(format!(
+                "Globe Chunk {}/{}/{}/{}",
+                key.face, key.depth, key.x, key.y
+            )),
+        )
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn stream_globe_chunks(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut chunks: ResMut<GlobeChunks>,
+    lod: Res<GlobeLod>,
+    globe: Res<Globe>,
+    height: Res<SphereHeightResource>,
+    origin: Res<WorldOrigin>,
+    cam_q: Query<&Transform, With<Camera3d>>,
+) {
+    if height.is_changed() || lod.is_changed() || globe.is_changed() {
+        chunks.invalidate();
+    }
+    let layout = GlobeLayout {
+        lod: &lod,
+        globe: &globe,
+        origin: &origin,
+        height: &height.0,
+    };
+    chunks.finish_builds(&mut commands, &mut meshes, &layout);
+
+    let Ok(cam) = cam_q.single() else {
+        return;
+    };
+    let eye = origin.to_world(cam.translation) - globe.center.as_dvec3();
+    let mut wanted = select_globe_chunks(&lod, globe.radius, eye);
+    wanted.sort_by(|a, b| {
+        a.distance(globe.radius, eye)
+            .total_cmp(&b.distance(globe.radius, eye))
+    });
+    chunks.update(&mut commands, &mut meshes, &layout, &wanted, lod.budget());
+}
+
+/// render-space position of a chunk's anchor
+fn chunk_translation(key: &GlobeChunkKey, globe: &Globe, origin: &WorldOrigin) -> Vec3 {
+    origin.to_render(globe.center.as_dvec3() + key.anchor(globe.radius))
+}
+
+/// moves the loaded chunks to where they sit relative to a new `WorldOrigin`
+pub(crate) fn follow_origin(
+    chunks: Res<GlobeChunks>,
+    globe: Res<Globe>,
+    origin: Res<WorldOrigin>,
+    mut q: Query<&mut Transform, With<GlobeChunk>>,
+) {
+    chunks.place(|key| chunk_translation(key, &globe, &origin), &mut q);
+}
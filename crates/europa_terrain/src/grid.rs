@@ -0,0 +1,94 @@
+use bevy::prelude::*;
+
+use crate::height::HeightSource;
+use crate::params::TerrainParams;
+
+/// regular grid of height samples, row-major with `i` along +x and `j` along +z
+#[derive(Clone)]
+pub struct Heightfield {
+    pub nx: usize,
+    pub nz: usize,
+    /// world xz of sample (0, 0)
+    pub origin: Vec2,
+    /// meters between neighbouring samples
+    pub spacing: f32,
+    pub data: Vec<f32>,
+}
+
+impl Heightfield {
+    pub fn sample(
+        height: &dyn HeightSource,
+        origin: Vec2,
+        spacing: f32,
+        nx: usize,
+        nz: usize,
+    ) -> Self {
+        let mut data = vec![0.0_f32; nx * nz];
+        height.sample_grid(origin.as_dvec2(), spacing, nx, nz, &mut data);
+        Self {
+            nx,
+            nz,
+            origin,
+            spacing,
+            data,
+        }
+    }
+
+    /// the `(res + 1)²` vertex grid `build_europa_mesh` lays over the params footprint
+    pub fn from_params(p: TerrainParams, height: &dyn HeightSource) -> Self {
+        let n = p.res as usize + 1;
+        let half = p.size * 0.5;
+        Self::sample(height, Vec2::splat(-half), p.size / p.res as f32, n, n)
+    }
+
+    pub fn get(&self, i: usize, j: usize) -> f32 {
+        self.data[j * self.nx + i]
+    }
+
+    pub fn min_max(&self) -> (f32, f32) {
+        self.data
+            .iter()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &h| {
+                (lo.min(h), hi.max(h))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::DVec2;
+
+    use super::*;
+
+    struct Plane;
+
+    impl HeightSource for Plane {
+        fn height_at_world(&self, p: DVec2) -> f32 {
+            (p.x + 10.0 * p.y) as f32
+        }
+    }
+
+    #[test]
+    fn samples_rows_along_z() {
+        let field = Heightfield::sample(&Plane, Vec2::new(-1.0, 2.0), 0.5, 3, 2);
+        assert_eq!(field.data.len(), 6);
+        assert_eq!(field.get(0, 0), 19.0);
+        assert_eq!(field.get(2, 0), 20.0);
+        assert_eq!(field.get(0, 1), 24.0);
+        assert_eq!(field.min_max(), (19.0, 25.0));
+    }
+
+    #[test]
+    fn from_params_covers_the_footprint() {
+        let p = TerrainParams {
+            size: 100.0,
+            res: 4,
+            ..TerrainParams::europa_demo()
+        };
+        let field = Heightfield::from_params(p, &Plane);
+        assert_eq!((field.nx, field.nz), (5, 5));
+        assert_eq!(field.origin, Vec2::splat(-50.0));
+        assert_eq!(field.spacing, 25.0);
+        assert_eq!(field.get(4, 4), 550.0);
+    }
+}
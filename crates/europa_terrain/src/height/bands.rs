@@ -0,0 +1,220 @@
+use bevy::math::DVec2;
+use bevy::prelude::*;
+use europa_math::{HashRng, hash_cell, smoothstep};
+
+use super::HeightSource;
+
+/// dilational bands: straight pull-apart zones with smooth, finely grooved floors.
+///
+/// the crust on either side of a band has moved apart by the band width, so
+/// outside the band `source` is sampled half a width back towards the centre
+/// line (plus some along-strike slip), and features cut by the band line up
+/// again when it is closed. bands are applied youngest first, so an older band
+/// is itself split and offset by a younger one
+pub struct Bands<S: HeightSource> {
+    pub source: S,
+    pub seed: u32,
+    /// bands per km²
+    pub density: f32,
+    /// meters
+    pub length_min: f32,
+    pub length_max: f32,
+    pub width_min: f32,
+    pub width_max: f32,
+    /// along-strike slip as a fraction of the width, either sense
+    pub shear: f32,
+    /// band floor height relative to the datum
+    pub floor_height: f32,
+    /// meters over which the walls ease into the floor
+    pub edge: f32,
+    /// height and spacing of the floor grooves, mirrored about the centre line
+    pub groove_amp: f32,
+    pub groove_spacing: f32,
+}
+
+impl<S: HeightSource> Bands<S> {
+    pub fn europa(source: S, seed: u32) -> Self {
+        Self {
+            source,
+            seed,
+            density: 0.08,
+            length_min: 4000.0,
+            length_max: 15000.0,
+            width_min: 150.0,
+            width_max: 800.0,
+            shear: 0.3,
+            floor_height: -0.5,
+            edge: 30.0,
+            groove_amp: 0.3,
+            groove_spacing: 40.0,
+        }
+    }
+
+    fn band(&self, seed: u32, cell: f32, ix: i32, iz: i32) -> Band {
+        let mut rng = HashRng(seed);
+        let jitter = DVec2::new(rng.next_f32().into(), rng.next_f32().into());
+        Band {
+            centre: (IVec2::new(ix, iz).as_dvec2() + jitter) * cell as f64,
+            dir: Vec2::from_angle(rng.range(0.0, std::f32::consts::PI)),
+            age: rng.next_f32(),
+            length: rng.range(self.length_min, self.length_max),
+            width: rng.range(self.width_min, self.width_max),
+            slip: rng.range(-self.shear, self.shear),
+            groove: self.groove_spacing * rng.range(0.8, 1.25),
+            phase: rng.range(0.0, std::f32::consts::TAU),
+        }
+    }
+
+    /// floor height at `d` across a band of half width `half`, grooves fade out at the walls
+    fn floor(&self, b: &Band, d: f32, half: f32) -> f32 {
+        let a = d.abs();
+        let wave = (a / b.groove * std::f32::consts::TAU + b.phase).cos()
+            + 0.5 * (a / b.groove * 2.7 * std::f32::consts::TAU).cos();
+        let fade = 1.0 - smoothstep(half - 2.0 * self.edge, half, a);
+        self.floor_height + self.groove_amp * wave / 1.5 * fade
+    }
+
+    /// undoes `bands` (youngest first) on top of `source`. only the offsets
+    /// from a band are single precision, `p` itself is carried in f64
+    fn sample(&self, bands: &[Band], mut p: DVec2) -> f32 {
+        for (k, b) in bands.iter().enumerate() {
+            let rel = (p - b.centre).as_vec2();
+            let s = rel.dot(b.dir);
+            let n = b.dir.perp();
+            let d = rel.dot(n);
+
+            // bands pinch shut towards their tips, and the plates either side
+            // take up the opening over a fraction of the band length
+            let open = 1.0 - smoothstep(0.3 * b.length, 0.5 * b.length, s.abs());
+            let half = 0.5 * b.width * open;
+            let reach = 0.3 * b.length;
+            if open <= 0.0 || d.abs() > half + reach {
+                continue;
+            }
+
+            let side = d.signum();
+            let closed = (n * side + b.dir * side * b.slip) * half;
+            if d.abs() < half {
+                let wall = p - (n * d + b.dir * side * b.slip * half).as_dvec2();
+                let wall = self.sample(&bands[k + 1..], wall);
+                let t = smoothstep(half - self.edge, half, d.abs());
+                return self.floor(b, d, half).lerp(wall, t);
+            }
+            p -= (closed * (1.0 - smoothstep(half, half + reach, d.abs()))).as_dvec2();
+        }
+
+        self.source.height_at_world(p)
+    }
+}
+
+struct Band {
+    centre: DVec2,
+    dir: Vec2,
+    age: f32,
+    length: f32,
+    width: f32,
+    slip: f32,
+    /// groove spacing and phase for this band
+    groove: f32,
+    phase: f32,
+}
+
+impl<S: HeightSource> HeightSource for Bands<S> {
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        let cell = self.length_max;
+        let lambda = self.density * 1e-6 * cell * cell;
+        let cx = (p.x / cell as f64).floor() as i32;
+        let cz = (p.y / cell as f64).floor() as i32;
+
+        let mut bands = Vec::new();
+        for dz in -1..=1 {
+            for dx in -1..=1 {
+                let (ix, iz) = (cx + dx, cz + dz);
+                let mut rng = HashRng(hash_cell(self.seed, ix, iz));
+                for _ in 0..rng.poisson(lambda) {
+                    bands.push(self.band(rng.next_u32(), cell, ix, iz));
+                }
+            }
+        }
+        bands.sort_by(|a, b| b.age.total_cmp(&a.age));
+        self.sample(&bands, p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::DVec2;
+
+    use super::*;
+
+    /// tilted plane, so every shift of the sample point shows up in the height
+    struct Plane;
+
+    impl HeightSource for Plane {
+        fn height_at_world(&self, p: DVec2) -> f32 {
+            (0.01 * p.x + 0.02 * p.y) as f32
+        }
+    }
+
+    /// one long band along +z through the origin, 200 m wide, slipping 0.3
+    fn band() -> Band {
+        Band {
+            centre: DVec2::ZERO,
+            dir: Vec2::Y,
+            age: 0.5,
+            length: 1.0e6,
+            width: 200.0,
+            slip: 0.3,
+            groove: 40.0,
+            phase: 0.7,
+        }
+    }
+
+    #[test]
+    fn walls_close_up_across_the_band() {
+        let bands = Bands::europa(Plane, 1);
+        let b = band();
+        let (half, eps) = (100.0_f32, 0.01_f32);
+        // `dir.perp()` is -x, so the +d wall lies towards -x
+        let plus = bands.sample(&[band()], DVec2::new(-(half + eps) as f64, 0.0));
+        let minus = bands.sample(&[band()], DVec2::new((half + eps) as f64, 0.0));
+
+        // each wall was pulled half a width out and slid half the slip along strike
+        let slide = b.slip * half;
+        assert!(
+            (plus - Plane.height_at(-eps, -slide)).abs() < 1e-4,
+            "{plus}"
+        );
+        assert!(
+            (minus - Plane.height_at(eps, slide)).abs() < 1e-4,
+            "{minus}"
+        );
+        let offset = minus - plus;
+        let expected = 0.01 * 2.0 * eps + 0.02 * b.slip * b.width;
+        assert!((offset - expected).abs() < 1e-4, "{offset} vs {expected}");
+    }
+
+    #[test]
+    fn interior_follows_the_infill_profile() {
+        let bands = Bands::europa(Plane, 1);
+        let b = band();
+        let half = 0.5 * b.width;
+        for d in [0.0, 12.5, 40.0, half - bands.edge - 0.1] {
+            for side in [1.0, -1.0] {
+                let h = bands.sample(&[band()], DVec2::new((-side * d) as f64, 250.0));
+                assert_eq!(h, bands.floor(&b, side * d, half), "d {}", side * d);
+            }
+            // grooves are mirrored about the centre line
+            assert_eq!(bands.floor(&b, d, half), bands.floor(&b, -d, half));
+        }
+
+        let smooth = Bands {
+            groove_amp: 0.0,
+            ..Bands::europa(Plane, 1)
+        };
+        assert_eq!(
+            smooth.sample(&[band()], DVec2::new(30.0, -75.0)),
+            smooth.floor_height
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use bevy::math::DVec2;
+use bevy::prelude::*;
+use europa_math::{HashRng, hash_cell, smoothstep};
+use noise::NoiseFn;
+
+use super::HeightSource;
+use super::noise::Perlin;
+
+/// Conamara-style chaos: the input terrain broken into Voronoi rafts that are
+/// shifted, rotated and tilted, floating in a lowered hummocky matrix.
+///
+/// every lattice cell holds one jittered Voronoi site; a share of them keep a raft
+/// showing `source` as it was before it moved, the rest melt into matrix
+pub struct ChaosTerrain<S: HeightSource> {
+    pub source: S,
+    pub seed: u32,
+    /// noise for the matrix hummocks
+    pub perlin: Perlin,
+    /// mean spacing between rafts, meters
+    pub raft_size: f32,
+    /// share of sites that keep a raft
+    pub raft_fraction: f32,
+    /// matrix between neighbouring rafts, meters
+    pub gap: f32,
+    /// width of the raft-to-matrix slope, meters
+    pub edge: f32,
+    /// how far a raft may have drifted from where it broke off, meters
+    pub max_shift: f32,
+    /// radians
+    pub max_rotation: f32,
+    /// surface slope a raft may be tilted to, m/m
+    pub max_tilt: f32,
+    /// vertical bob of a whole raft, meters
+    pub max_lift: f32,
+    /// matrix floor below the datum, meters
+    pub matrix_depth: f32,
+    pub hummock_amp: f32,
+    pub hummock_freq: f32,
+}
+
+impl<S: HeightSource> ChaosTerrain<S> {
+    /// tuned to break up the demo stack
+    pub fn europa(source: S, seed: u32) -> Self {
+        Self {
+            source,
+            seed,
+            perlin: Perlin::new(seed ^ 0x2c1b_3c6d),
+            raft_size: 1200.0,
+            raft_fraction: 0.6,
+            gap: 120.0,
+            edge: 60.0,
+            max_shift: 300.0,
+            max_rotation: 0.5,
+            max_tilt: 0.003,
+            max_lift: 1.0,
+            matrix_depth: 4.0,
+            hummock_amp: 2.0,
+            hummock_freq: 1.0 / 80.0,
+        }
+    }
+
+    fn site(&self, ix: i32, iz: i32) -> DVec2 {
+        let mut rng = HashRng(hash_cell(self.seed, ix, iz));
+        let jitter = DVec2::new(rng.next_f32().into(), rng.next_f32().into());
+        (IVec2::new(ix, iz).as_dvec2() + jitter) * self.raft_size as f64
+    }
+
+    fn matrix(&self, p: DVec2) -> f32 {
+        // knobbly |fbm| reads as jumbled blocks rather than rolling hills
+        let mut a = 1.0;
+        let mut sum = 0.0;
+        let mut amp = 0.0;
+        let mut f = p * self.hummock_freq as f64;
+        for _ in 0..3 {
+            sum += a * (self.perlin.get(f.to_array()) as f32).abs();
+            amp += a;
+            f *= 2.0;
+            a *= 0.5;
+        }
+        -self.matrix_depth + (sum / amp) * self.hummock_amp
+    }
+}
+
+impl<S: HeightSource> HeightSource for ChaosTerrain<S> {
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        let cell = (p / self.raft_size as f64).floor();
+        let (cx, cz) = (cell.x as i32, cell.y as i32);
+
+        let sites: [(IVec2, DVec2); 9] = std::array::from_fn(|k| {
+            let cell = IVec2::new(cx + k as i32 % 3 - 1, cz + k as i32 / 3 - 1);
+            (cell, self.site(cell.x, cell.y))
+        });
+        let nearest = (0..9)
+            .min_by(|&a, &b| {
+                p.distance_squared(sites[a].1)
+                    .total_cmp(&p.distance_squared(sites[b].1))
+            })
+            .unwrap_or(4);
+        let (cell, c) = sites[nearest];
+
+        // distance to the closest Voronoi edge, i.e. to the nearest bisector
+        let border = sites
+            .iter()
+            .enumerate()
+            .filter(|&(k, _)| k != nearest)
+            .map(|(_, &(_, o))| (p - (c + o) * 0.5).dot((o - c).normalize()).abs())
+            .fold(f64::INFINITY, f64::min) as f32;
+
+        let mut rng = HashRng(hash_cell(self.seed ^ 0x5bd1_e995, cell.x, cell.y));
+        let matrix = self.matrix(p);
+        if rng.next_f32() >= self.raft_fraction {
+            return matrix;
+        }
+
+        let shift = Vec2::new(rng.range(-1.0, 1.0), rng.range(-1.0, 1.0)) * self.max_shift;
+        let turn = rng.range(-self.max_rotation, self.max_rotation);
+        let tilt = Vec2::new(rng.range(-1.0, 1.0), rng.range(-1.0, 1.0)) * self.max_tilt;
+        let lift = rng.range(-self.max_lift, self.max_lift);
+
+        // undo the raft's motion to find where this point broke off from. the
+        // offset from the site is small, the site itself may be far out
+        let local = (p - c).as_vec2();
+        let origin = c - shift.as_dvec2() + Vec2::from_angle(-turn).rotate(local).as_dvec2();
+        let raft = self.source.height_at_world(origin) + tilt.dot(local) + lift;
+
+        let half_gap = self.gap * 0.5;
+        let cover = smoothstep(half_gap, half_gap + self.edge, border);
+        matrix.lerp(raft, cover)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::DVec2;
+
+    use super::*;
+
+    struct Flat(f32);
+
+    impl HeightSource for Flat {
+        fn height_at_world(&self, _: DVec2) -> f32 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn rafts_stand_above_the_matrix() {
+        // narrow gaps so every site sits well inside its own raft
+        let chaos = ChaosTerrain {
+            gap: 20.0,
+            edge: 10.0,
+            ..ChaosTerrain::europa(Flat(3.0), 9)
+        };
+        let mut rafts = 0;
+        for iz in -4..4 {
+            for ix in -4..4 {
+                let c = chaos.site(ix, iz);
+                let h = chaos.height_at_world(c);
+                let matrix = chaos.matrix(c);
+                let mut rng = HashRng(hash_cell(chaos.seed ^ 0x5bd1_e995, ix, iz));
+                if rng.next_f32() >= chaos.raft_fraction {
+                    assert_eq!(h, matrix);
+                    continue;
+                }
+                rafts += 1;
+                // untilted at its own site, only bobbed by the lift
+                assert!((h - 3.0).abs() <= chaos.max_lift + 1e-4, "raft at {h}");
+                assert!(h > matrix + 1.0, "raft {h} sunk into matrix {matrix}");
+            }
+        }
+        assert!((20..60).contains(&rafts), "{rafts} of 64 sites kept a raft");
+    }
+
+    #[test]
+    fn heights_are_continuous_across_raft_borders() {
+        let chaos = ChaosTerrain::europa(Flat(3.0), 9);
+        let step = 0.25;
+        let mut prev = chaos.height_at(-3000.0, 170.0);
+        let (mut lo, mut hi) = (prev, prev);
+        for k in 1..(6000.0 / step) as usize {
+            let h = chaos.height_at(-3000.0 + k as f32 * step, 170.0);
+            assert!(
+                (h - prev).abs() < 0.1,
+                "step of {} at x {}",
+                h - prev,
+                k as f32 * step
+            );
+            (lo, hi, prev) = (lo.min(h), hi.max(h), h);
+        }
+        // the walk crossed both rafts and matrix
+        assert!(lo < -1.0 && hi > 2.0, "{lo}..{hi}");
+    }
+}
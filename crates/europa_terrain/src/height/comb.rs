@@ -1,12 +1,47 @@
-use super::HeightSource;
+use bevy::math::DVec2;
+use bevy::prelude::*;
+use europa_math::smoothstep;
+use serde::{Deserialize, Serialize};
+
+use super::{HeightFn, HeightSource};
 
 pub struct Add2<A: HeightSource, B: HeightSource> {
     pub a: A,
     pub b: B,
 }
 impl<A: HeightSource, B: HeightSource> HeightSource for Add2<A, B> {
-    fn height_at(&self, x: f32, z: f32) -> f32 {
-        self.a.height_at(x, z) + self.b.height_at(x, z)
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        self.a.height_at_world(p) + self.b.height_at_world(p)
+    }
+
+    fn height_and_gradient_world(&self, p: DVec2) -> (f32, Vec2) {
+        let (ha, ga) = self.a.height_and_gradient_world(p);
+        let (hb, gb) = self.b.height_and_gradient_world(p);
+        (ha + hb, ga + gb)
+    }
+
+    fn sample_points(&self, points: &[DVec2], out: &mut [f32]) {
+        self.a.sample_points(points, out);
+        let mut b = vec![0.0; points.len()];
+        self.b.sample_points(points, &mut b);
+        for (h, b) in out.iter_mut().zip(b) {
+            *h += b;
+        }
+    }
+
+    fn sample_points_with_gradient(&self, points: &[DVec2], out: &mut [f32], grad: &mut [Vec2]) {
+        self.a.sample_points_with_gradient(points, out, grad);
+        let mut hb = vec![0.0; points.len()];
+        let mut gb = vec![Vec2::ZERO; points.len()];
+        self.b.sample_points_with_gradient(points, &mut hb, &mut gb);
+        for ((h, g), (hb, gb)) in out
+            .iter_mut()
+            .zip(grad.iter_mut())
+            .zip(hb.into_iter().zip(gb))
+        {
+            *h += hb;
+            *g += gb;
+        }
     }
 }
 
@@ -15,8 +50,30 @@ pub struct Scale<S: HeightSource> {
     pub scale: f32,
 }
 impl<S: HeightSource> HeightSource for Scale<S> {
-    fn height_at(&self, x: f32, z: f32) -> f32 {
-        self.s.height_at(x, z) * self.scale
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        self.s.height_at_world(p) * self.scale
+    }
+
+    fn height_and_gradient_world(&self, p: DVec2) -> (f32, Vec2) {
+        let (h, g) = self.s.height_and_gradient_world(p);
+        (h * self.scale, g * self.scale)
+    }
+
+    fn sample_points(&self, points: &[DVec2], out: &mut [f32]) {
+        self.s.sample_points(points, out);
+        out[..points.len()]
+            .iter_mut()
+            .for_each(|h| *h *= self.scale);
+    }
+
+    fn sample_points_with_gradient(&self, points: &[DVec2], out: &mut [f32], grad: &mut [Vec2]) {
+        self.s.sample_points_with_gradient(points, out, grad);
+        out[..points.len()]
+            .iter_mut()
+            .for_each(|h| *h *= self.scale);
+        grad[..points.len()]
+            .iter_mut()
+            .for_each(|g| *g *= self.scale);
     }
 }
 
@@ -25,7 +82,471 @@ pub struct Bias<S: HeightSource> {
     pub bias: f32,
 }
 impl<S: HeightSource> HeightSource for Bias<S> {
-    fn height_at(&self, x: f32, z: f32) -> f32 {
-        self.s.height_at(x, z) + self.bias
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        self.s.height_at_world(p) + self.bias
+    }
+
+    fn height_and_gradient_world(&self, p: DVec2) -> (f32, Vec2) {
+        let (h, g) = self.s.height_and_gradient_world(p);
+        (h + self.bias, g)
+    }
+
+    fn sample_points(&self, points: &[DVec2], out: &mut [f32]) {
+        self.s.sample_points(points, out);
+        out[..points.len()].iter_mut().for_each(|h| *h += self.bias);
+    }
+
+    fn sample_points_with_gradient(&self, points: &[DVec2], out: &mut [f32], grad: &mut [Vec2]) {
+        self.s.sample_points_with_gradient(points, out, grad);
+        out[..points.len()].iter_mut().for_each(|h| *h += self.bias);
+    }
+}
+
+pub struct Mul<A: HeightSource, B: HeightSource> {
+    pub a: A,
+    pub b: B,
+}
+impl<A: HeightSource, B: HeightSource> HeightSource for Mul<A, B> {
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        self.a.height_at_world(p) * self.b.height_at_world(p)
+    }
+
+    fn height_and_gradient_world(&self, p: DVec2) -> (f32, Vec2) {
+        let (ha, ga) = self.a.height_and_gradient_world(p);
+        let (hb, gb) = self.b.height_and_gradient_world(p);
+        (ha * hb, ga * hb + gb * ha)
+    }
+}
+
+pub struct Min<A: HeightSource, B: HeightSource> {
+    pub a: A,
+    pub b: B,
+}
+impl<A: HeightSource, B: HeightSource> HeightSource for Min<A, B> {
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        self.a.height_at_world(p).min(self.b.height_at_world(p))
+    }
+
+    fn height_and_gradient_world(&self, p: DVec2) -> (f32, Vec2) {
+        let a = self.a.height_and_gradient_world(p);
+        let b = self.b.height_and_gradient_world(p);
+        if a.0 <= b.0 { a } else { b }
+    }
+}
+
+pub struct Max<A: HeightSource, B: HeightSource> {
+    pub a: A,
+    pub b: B,
+}
+impl<A: HeightSource, B: HeightSource> HeightSource for Max<A, B> {
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        self.a.height_at_world(p).max(self.b.height_at_world(p))
+    }
+
+    fn height_and_gradient_world(&self, p: DVec2) -> (f32, Vec2) {
+        let a = self.a.height_and_gradient_world(p);
+        let b = self.b.height_and_gradient_world(p);
+        if a.0 >= b.0 { a } else { b }
+    }
+}
+
+/// polynomial smooth minimum, blends over a band `k` meters wide where the inputs cross
+pub struct SmoothMin<A: HeightSource, B: HeightSource> {
+    pub a: A,
+    pub b: B,
+    pub k: f32,
+}
+impl<A: HeightSource, B: HeightSource> HeightSource for SmoothMin<A, B> {
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        smooth_min(self.a.height_at_world(p), self.b.height_at_world(p), self.k)
+    }
+}
+
+/// polynomial smooth maximum, the mirror of `SmoothMin`
+pub struct SmoothMax<A: HeightSource, B: HeightSource> {
+    pub a: A,
+    pub b: B,
+    pub k: f32,
+}
+impl<A: HeightSource, B: HeightSource> HeightSource for SmoothMax<A, B> {
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        -smooth_min(
+            -self.a.height_at_world(p),
+            -self.b.height_at_world(p),
+            self.k,
+        )
+    }
+}
+
+pub fn smooth_min(a: f32, b: f32, k: f32) -> f32 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+    b + (a - b) * h - k * h * (1.0 - h)
+}
+
+pub struct Clamp<S: HeightSource> {
+    pub s: S,
+    pub min: f32,
+    pub max: f32,
+}
+impl<S: HeightSource> HeightSource for Clamp<S> {
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        // unlike `f32::clamp` this can't panic on bounds the other way round
+        self.s.height_at_world(p).max(self.min).min(self.max)
+    }
+
+    fn height_and_gradient_world(&self, p: DVec2) -> (f32, Vec2) {
+        let (h, g) = self.s.height_and_gradient_world(p);
+        if h < self.min || h > self.max {
+            (h.max(self.min).min(self.max), Vec2::ZERO)
+        } else {
+            (h, g)
+        }
+    }
+}
+
+pub struct Abs<S: HeightSource> {
+    pub s: S,
+}
+impl<S: HeightSource> HeightSource for Abs<S> {
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        self.s.height_at_world(p).abs()
+    }
+
+    fn height_and_gradient_world(&self, p: DVec2) -> (f32, Vec2) {
+        let (h, g) = self.s.height_and_gradient_world(p);
+        (h.abs(), if h < 0.0 { -g } else { g })
+    }
+}
+
+/// quantizes heights into steps `step` meters tall. `sharpness` 0 leaves the
+/// input alone, 1 gives vertical risers
+pub struct Terrace<S: HeightSource> {
+    pub s: S,
+    pub step: f32,
+    pub sharpness: f32,
+}
+impl<S: HeightSource> HeightSource for Terrace<S> {
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        let h = self.s.height_at_world(p);
+        if self.step <= 0.0 {
+            return h;
+        }
+        let t = h / self.step;
+        let base = t.floor();
+        // the riser takes up the middle (1 - sharpness) of every step
+        let e = 0.5 * self.sharpness.clamp(0.0, 0.999);
+        let f = smoothstep(e, 1.0 - e, t - base);
+        let f = (t - base).lerp(f, self.sharpness.clamp(0.0, 1.0));
+        (base + f) * self.step
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CurveInterp {
+    Linear,
+    /// catmull-rom through the control points
+    Spline,
+}
+
+/// remaps heights through control points `(input, output)` sorted by input,
+/// flat past either end
+pub struct Curve<S: HeightSource> {
+    pub s: S,
+    pub points: Vec<Vec2>,
+    pub interp: CurveInterp,
+}
+impl<S: HeightSource> Curve<S> {
+    pub fn map(&self, h: f32) -> f32 {
+        let p = &self.points;
+        match p.len() {
+            0 => return h,
+            1 => return p[0].y,
+            _ => {}
+        }
+        if h <= p[0].x {
+            return p[0].y;
+        }
+        if h >= p[p.len() - 1].x {
+            return p[p.len() - 1].y;
+        }
+
+        let i = p.partition_point(|c| c.x <= h) - 1;
+        let (p1, p2) = (p[i], p[i + 1]);
+        let span = p2.x - p1.x;
+        let t = if span > 0.0 { (h - p1.x) / span } else { 0.0 };
+        match self.interp {
+            CurveInterp::Linear => p1.y.lerp(p2.y, t),
+            CurveInterp::Spline => {
+                let p0 = p[i.saturating_sub(1)].y;
+                let p3 = p[(i + 2).min(p.len() - 1)].y;
+                let (y1, y2) = (p1.y, p2.y);
+                let t2 = t * t;
+                0.5 * (2.0 * y1
+                    + (y2 - p0) * t
+                    + (2.0 * p0 - 5.0 * y1 + 4.0 * y2 - p3) * t2
+                    + (3.0 * y1 - p0 - 3.0 * y2 + p3) * t2 * t)
+            }
+        }
+    }
+}
+impl<S: HeightSource> HeightSource for Curve<S> {
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        self.map(self.s.height_at_world(p))
+    }
+}
+
+/// blends `a` into `b` by `mask`, clamped to 0..1
+pub struct Lerp<A: HeightSource, B: HeightSource, M: HeightSource> {
+    pub a: A,
+    pub b: B,
+    pub mask: M,
+}
+impl<A: HeightSource, B: HeightSource, M: HeightSource> HeightSource for Lerp<A, B, M> {
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        let t = self.mask.height_at_world(p).clamp(0.0, 1.0);
+        self.a.height_at_world(p).lerp(self.b.height_at_world(p), t)
+    }
+
+    fn height_and_gradient_world(&self, p: DVec2) -> (f32, Vec2) {
+        let (ha, ga) = self.a.height_and_gradient_world(p);
+        let (hb, gb) = self.b.height_and_gradient_world(p);
+        let (m, gm) = self.mask.height_and_gradient_world(p);
+        let t = m.clamp(0.0, 1.0);
+        let gt = if (0.0..=1.0).contains(&m) {
+            gm
+        } else {
+            Vec2::ZERO
+        };
+        (ha.lerp(hb, t), ga.lerp(gb, t) + gt * (hb - ha))
+    }
+}
+
+/// `a` where `mask` is below `threshold`, `b` above it, blended over `falloff`
+/// either side of the threshold
+pub struct Select<A: HeightSource, B: HeightSource, M: HeightSource> {
+    pub a: A,
+    pub b: B,
+    pub mask: M,
+    pub threshold: f32,
+    pub falloff: f32,
+}
+impl<A: HeightSource, B: HeightSource, M: HeightSource> HeightSource for Select<A, B, M> {
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        let m = self.mask.height_at_world(p);
+        let t = if self.falloff > 0.0 {
+            smoothstep(
+                self.threshold - self.falloff,
+                self.threshold + self.falloff,
+                m,
+            )
+        } else if m < self.threshold {
+            0.0
+        } else {
+            1.0
+        };
+        // skip the side that doesn't contribute
+        match t {
+            0.0 => self.a.height_at_world(p),
+            1.0 => self.b.height_at_world(p),
+            _ => self.a.height_at_world(p).lerp(self.b.height_at_world(p), t),
+        }
+    }
+}
+
+/// n-ary sum
+pub struct Sum {
+    pub items: Vec<HeightFn>,
+}
+impl HeightSource for Sum {
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        self.items.iter().map(|s| s.height_at_world(p)).sum()
+    }
+
+    fn height_and_gradient_world(&self, p: DVec2) -> (f32, Vec2) {
+        self.items
+            .iter()
+            .map(|s| s.height_and_gradient_world(p))
+            .fold((0.0, Vec2::ZERO), |(h, g), (sh, sg)| (h + sh, g + sg))
+    }
+
+    fn sample_points(&self, points: &[DVec2], out: &mut [f32]) {
+        let out = &mut out[..points.len()];
+        out.fill(0.0);
+        let mut tmp = vec![0.0; points.len()];
+        for s in &self.items {
+            s.sample_points(points, &mut tmp);
+            for (h, t) in out.iter_mut().zip(&tmp) {
+                *h += t;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::height::arc;
+
+    /// h = c + slope · (x, z)
+    struct Plane {
+        c: f32,
+        slope: Vec2,
+    }
+    impl HeightSource for Plane {
+        fn height_at_world(&self, p: DVec2) -> f32 {
+            self.c + self.slope.dot(p.as_vec2())
+        }
+    }
+
+    fn flat(c: f32) -> Plane {
+        Plane {
+            c,
+            slope: Vec2::ZERO,
+        }
+    }
+
+    fn ramp() -> Plane {
+        Plane {
+            c: 0.0,
+            slope: Vec2::X,
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        let mul = Mul {
+            a: flat(3.0),
+            b: ramp(),
+        };
+        assert_eq!(mul.height_at(2.0, 0.0), 6.0);
+        assert!((mul.height_and_gradient(2.0, 0.0).1 - Vec2::new(3.0, 0.0)).length() < 1e-3);
+
+        let abs = Abs { s: ramp() };
+        assert_eq!(abs.height_at(-4.0, 0.0), 4.0);
+        assert!((abs.height_and_gradient(-4.0, 0.0).1 - Vec2::new(-1.0, 0.0)).length() < 1e-3);
+
+        let clamp = Clamp {
+            s: ramp(),
+            min: -1.0,
+            max: 2.0,
+        };
+        assert_eq!(clamp.height_at(5.0, 0.0), 2.0);
+        assert_eq!(clamp.height_at(-5.0, 0.0), -1.0);
+        assert_eq!(clamp.height_at(0.5, 0.0), 0.5);
+        // crossed bounds settle on `max` rather than panicking
+        let crossed = Clamp {
+            s: ramp(),
+            min: 2.0,
+            max: -1.0,
+        };
+        assert_eq!(crossed.height_at(0.5, 0.0), -1.0);
+        assert_eq!(crossed.height_and_gradient(0.5, 0.0), (-1.0, Vec2::ZERO));
+
+        let sum = Sum {
+            items: vec![arc(flat(1.0)), arc(flat(2.0)), arc(ramp())],
+        };
+        assert_eq!(sum.height_at(4.0, 0.0), 7.0);
+        let mut grid = [0.0; 3];
+        sum.sample_grid(DVec2::ZERO, 1.0, 3, 1, &mut grid);
+        assert_eq!(grid, [3.0, 4.0, 5.0]);
+    }
+
+    #[test]
+    fn min_max_and_smooth_variants() {
+        let (a, b) = (|| flat(1.0), ramp);
+        assert_eq!(Min { a: a(), b: b() }.height_at(3.0, 0.0), 1.0);
+        assert_eq!(Max { a: a(), b: b() }.height_at(3.0, 0.0), 3.0);
+
+        // smooth versions agree away from the crossing and round it off nearby
+        let smin = SmoothMin {
+            a: a(),
+            b: b(),
+            k: 0.5,
+        };
+        let smax = SmoothMax {
+            a: a(),
+            b: b(),
+            k: 0.5,
+        };
+        assert_eq!(smin.height_at(3.0, 0.0), 1.0);
+        assert_eq!(smax.height_at(-3.0, 0.0), 1.0);
+        assert!(smin.height_at(1.0, 0.0) < 1.0);
+        assert!(smax.height_at(1.0, 0.0) > 1.0);
+    }
+
+    #[test]
+    fn terrace_steps() {
+        let hard = Terrace {
+            s: ramp(),
+            step: 2.0,
+            sharpness: 1.0,
+        };
+        assert!((hard.height_at(0.2, 0.0) - 0.0).abs() < 1e-4);
+        assert!((hard.height_at(1.8, 0.0) - 2.0).abs() < 1e-4);
+        assert!((hard.height_at(2.3, 0.0) - 2.0).abs() < 1e-4);
+
+        let soft = Terrace {
+            s: ramp(),
+            step: 2.0,
+            sharpness: 0.0,
+        };
+        assert!((soft.height_at(1.3, 0.0) - 1.3).abs() < 1e-5);
+    }
+
+    #[test]
+    fn curve_remaps() {
+        let points = vec![
+            Vec2::new(0.0, 0.0),
+            Vec2::new(1.0, 10.0),
+            Vec2::new(2.0, 0.0),
+        ];
+        let linear = Curve {
+            s: ramp(),
+            points: points.clone(),
+            interp: CurveInterp::Linear,
+        };
+        assert_eq!(linear.height_at(0.5, 0.0), 5.0);
+        assert_eq!(linear.height_at(-3.0, 0.0), 0.0);
+        assert_eq!(linear.height_at(9.0, 0.0), 0.0);
+
+        let spline = Curve {
+            s: ramp(),
+            points,
+            interp: CurveInterp::Spline,
+        };
+        // passes through the control points, bulges above the chord between them
+        assert!((spline.height_at(1.0, 0.0) - 10.0).abs() < 1e-5);
+        assert!(spline.height_at(0.5, 0.0) > 5.0);
+    }
+
+    #[test]
+    fn lerp_and_select() {
+        let lerp = Lerp {
+            a: flat(0.0),
+            b: flat(10.0),
+            mask: flat(0.25),
+        };
+        assert_eq!(lerp.height_at(0.0, 0.0), 2.5);
+
+        let select = Select {
+            a: flat(-1.0),
+            b: flat(1.0),
+            mask: ramp(),
+            threshold: 0.0,
+            falloff: 0.5,
+        };
+        assert_eq!(select.height_at(-1.0, 0.0), -1.0);
+        assert_eq!(select.height_at(1.0, 0.0), 1.0);
+        assert_eq!(select.height_at(0.0, 0.0), 0.0);
+
+        let hard = Select {
+            falloff: 0.0,
+            ..select
+        };
+        assert_eq!(hard.height_at(-0.01, 0.0), -1.0);
+        assert_eq!(hard.height_at(0.01, 0.0), 1.0);
     }
 }
@@ -0,0 +1,164 @@
+use bevy::math::DVec2;
+use europa_math::{HashRng, hash_cell, hash_u32, smoothstep};
+
+use super::HeightSource;
+
+/// impact craters scattered from a seed, heights relative to the surface (0 far away).
+///
+/// diameters follow a cumulative power law `N(>D) = density * (D / d_min)^-slope`
+/// per km². the range is split into octave bins, each bin scatters into its own
+/// lattice whose cells are as wide as that bin's ejecta reach, so a lookup only
+/// ever touches the 3x3 cells around the sample
+pub struct Craters {
+    pub seed: u32,
+    /// craters at least `d_min` wide per km²
+    pub density: f32,
+    /// diameter range in meters
+    pub d_min: f32,
+    pub d_max: f32,
+    /// cumulative sfd exponent, ~2 for Europa's small craters
+    pub slope: f32,
+    /// bowl depth / diameter for simple craters
+    pub depth_ratio: f32,
+    /// rim height / diameter
+    pub rim_ratio: f32,
+    /// simple-to-complex transition: wider craters get flat floors, central
+    /// peaks and shallower bowls
+    pub complex_diameter: f32,
+    /// how far ejecta reaches, in crater radii
+    pub ejecta_extent: f32,
+}
+
+impl Craters {
+    /// sparse, fresh-looking small craters on top of the demo patch
+    pub fn europa(seed: u32) -> Self {
+        Self {
+            seed,
+            density: 2.0,
+            d_min: 20.0,
+            d_max: 1500.0,
+            slope: 2.0,
+            depth_ratio: 0.15,
+            rim_ratio: 0.035,
+            complex_diameter: 4000.0,
+            ejecta_extent: 3.0,
+        }
+    }
+
+    /// craters per m² in `[lo, hi)`
+    fn bin_density(&self, lo: f32, hi: f32) -> f32 {
+        let n = |d: f32| (d / self.d_min).powf(-self.slope);
+        self.density * 1e-6 * (n(lo) - n(hi))
+    }
+
+    /// inverse cdf of the power law truncated to `[lo, hi)`
+    fn sample_diameter(&self, lo: f32, hi: f32, u: f32) -> f32 {
+        let k = 1.0 - (hi / lo).powf(-self.slope);
+        lo * (1.0 - u * k).powf(-1.0 / self.slope)
+    }
+
+    /// height of a single crater of diameter `d` at distance `dist` from its centre
+    fn profile(&self, d: f32, dist: f32) -> f32 {
+        let radius = d * 0.5;
+        let r = dist / radius;
+        if r >= self.ejecta_extent {
+            return 0.0;
+        }
+
+        let complex = d > self.complex_diameter;
+        let mut depth = self.depth_ratio * d;
+        if complex {
+            // large craters collapse, depth grows slower than diameter
+            depth *= (self.complex_diameter / d).sqrt();
+        }
+        let rim = self.rim_ratio * d;
+
+        if r >= 1.0 {
+            // ejecta thins as r^-3, shifted so it ends exactly at the extent
+            let e = self.ejecta_extent.powi(-3);
+            return rim * (r.powi(-3) - e) / (1.0 - e);
+        }
+
+        if !complex {
+            // parabolic bowl from -depth at the centre to the rim crest
+            return -depth + (depth + rim) * r * r;
+        }
+
+        // flat floor out to ~half the radius, then a wall up to the rim
+        let floor = 0.5;
+        let wall = smoothstep(floor, 1.0, r);
+        let mut h = -depth + (depth + rim) * wall * wall;
+        let peak_r = 0.18;
+        h += 0.4 * depth * (1.0 - smoothstep(0.0, peak_r, r));
+        h
+    }
+}
+
+impl HeightSource for Craters {
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        // the bins double from d_min, so they'd never reach d_max from zero or below
+        if !(self.d_min > 0.0 && self.d_max.is_finite()) {
+            return 0.0;
+        }
+        let mut sum = 0.0;
+        let mut lo = self.d_min;
+        let mut bin = 0u32;
+
+        while lo < self.d_max {
+            let hi = (lo * 2.0).min(self.d_max);
+            let cell = hi * 0.5 * self.ejecta_extent;
+            let lambda = self.bin_density(lo, hi) * cell * cell;
+            let bin_seed = hash_u32(self.seed ^ bin.wrapping_mul(0x68e3_1da4));
+
+            let cx = (p.x / cell as f64).floor() as i32;
+            let cz = (p.y / cell as f64).floor() as i32;
+            for dz in -1..=1 {
+                for dx in -1..=1 {
+                    let (ix, iz) = (cx + dx, cz + dz);
+                    let mut rng = HashRng(hash_cell(bin_seed, ix, iz));
+                    for _ in 0..rng.poisson(lambda) {
+                        let px = (ix as f64 + rng.next_f32() as f64) * cell as f64;
+                        let pz = (iz as f64 + rng.next_f32() as f64) * cell as f64;
+                        let d = self.sample_diameter(lo, hi, rng.next_f32());
+                        let dist = p.distance(DVec2::new(px, pz)) as f32;
+                        sum += self.profile(d, dist);
+                    }
+                }
+            }
+
+            lo = hi;
+            bin += 1;
+        }
+
+        sum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_is_continuous_at_rim_and_fades_out() {
+        let c = Craters::europa(1);
+        for d in [100.0, 8000.0] {
+            let r = d * 0.5;
+            let inside = c.profile(d, r * 0.9999);
+            let outside = c.profile(d, r * 1.0001);
+            assert!((inside - outside).abs() < d * 1e-3, "rim step at D={d}");
+            assert!(c.profile(d, 0.0) < 0.0, "floor below datum at D={d}");
+            assert!(c.profile(d, r * c.ejecta_extent * 0.999).abs() < d * 1e-5);
+        }
+    }
+
+    #[test]
+    fn degenerate_diameter_range_is_flat() {
+        for d_min in [0.0, -10.0] {
+            let c = Craters {
+                d_min,
+                ..Craters::europa(1)
+            };
+            assert_eq!(c.height_at(35.0, -80.0), 0.0);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use bevy::math::DVec2;
+use bevy::prelude::*;
+
+use super::HeightSource;
+
+/// `source` bent onto a sphere of `radius` that touches the datum at `center`:
+/// each sample drops by the sagitta and tilts with the surface, so horizons,
+/// line of sight and shadows come out as on a real moon. the horizontal
+/// position is left alone, which is off by `d³ / 6R²` (half a meter 20 km out
+/// on Europa)
+pub struct Curved<S: HeightSource> {
+    pub source: S,
+    pub radius: f32,
+    pub center: Vec2,
+}
+
+impl<S: HeightSource> Curved<S> {
+    fn bend(&self, p: DVec2, h: f32, grad: Vec2) -> (f32, Vec2) {
+        bend(self.radius, (p - self.center.as_dvec2()).as_vec2(), h, grad)
+    }
+}
+
+/// height and gradient of the bent surface from the flat ones, `rel` meters
+/// from where the sphere touches the datum
+pub(crate) fn bend(radius: f32, rel: Vec2, h: f32, grad: Vec2) -> (f32, Vec2) {
+    let theta = rel.length() / radius;
+    let (sin, cos) = theta.sin_cos();
+    // R (1 - cos θ) without the cancellation
+    let sag = 2.0 * radius * (0.5 * theta).sin().powi(2);
+    let sinc = if theta < 1e-4 { 1.0 } else { sin / theta };
+    // d/dp of (h + R) cos θ, with dθ/dp = rel / (d R)
+    let grad = grad * cos - rel * ((h + radius) * sinc / (radius * radius));
+    (h * cos - sag, grad)
+}
+
+impl<S: HeightSource> HeightSource for Curved<S> {
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        self.bend(p, self.source.height_at_world(p), Vec2::ZERO).0
+    }
+
+    fn height_and_gradient_world(&self, p: DVec2) -> (f32, Vec2) {
+        let (h, grad) = self.source.height_and_gradient_world(p);
+        self.bend(p, h, grad)
+    }
+
+    fn sample_points(&self, points: &[DVec2], out: &mut [f32]) {
+        self.source.sample_points(points, out);
+        for (h, &p) in out.iter_mut().zip(points) {
+            *h = self.bend(p, *h, Vec2::ZERO).0;
+        }
+    }
+
+    fn sample_points_with_gradient(&self, points: &[DVec2], out: &mut [f32], grad: &mut [Vec2]) {
+        self.source.sample_points_with_gradient(points, out, grad);
+        for ((h, g), &p) in out.iter_mut().zip(grad.iter_mut()).zip(points) {
+            (*h, *g) = self.bend(p, *h, *g);
+        }
+    }
+}
@@ -0,0 +1,824 @@
+//! elevation rasters (PDS3 IMG and single-band GeoTIFF) as a height source
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use bevy::math::DVec2;
+use bevy::prelude::*;
+use thiserror::Error;
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::tags::Tag;
+
+use super::HeightSource;
+use crate::params::EUROPA_RADIUS;
+
+#[derive(Debug, Error)]
+pub enum DemError {
+    #[error("could not read DEM: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("bad GeoTIFF: {0}")]
+    Tiff(#[from] tiff::TiffError),
+    #[error("bad PDS label: {0}")]
+    Label(String),
+    #[error("unsupported DEM: {0}")]
+    Unsupported(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum DemInterp {
+    #[default]
+    Bilinear,
+    /// catmull-rom, falls back to bilinear next to nodata
+    Bicubic,
+}
+
+/// gridded elevation model in meters.
+///
+/// column `i` runs along +x and row `j` along +z, `origin` being the world xz
+/// of sample (0, 0). nodata samples are stored as NaN and skipped when
+/// interpolating
+pub struct Dem {
+    pub cols: usize,
+    pub rows: usize,
+    pub data: Vec<f32>,
+    /// ground spacing between samples in meters
+    pub pixel_size: f32,
+    pub origin: Vec2,
+    pub interp: DemInterp,
+    /// height returned off the raster or where nothing valid is nearby
+    pub fallback: f32,
+}
+
+impl Dem {
+    /// raster centred on the world origin. `data` is row-major, `cols * rows` long
+    pub fn from_samples(
+        cols: usize,
+        rows: usize,
+        data: Vec<f32>,
+        pixel_size: f32,
+    ) -> Result<Self, DemError> {
+        if cols == 0 || rows == 0 {
+            return Err(DemError::Unsupported(format!("empty {cols}x{rows} raster")));
+        }
+        if !(pixel_size.is_finite() && pixel_size > 0.0) {
+            return Err(DemError::Unsupported(format!("pixel size {pixel_size}")));
+        }
+        if data.len() != cols * rows {
+            return Err(DemError::Unsupported(format!(
+                "{cols}x{rows} raster with {} samples",
+                data.len()
+            )));
+        }
+        let dem = Self {
+            cols,
+            rows,
+            data,
+            pixel_size,
+            origin: Vec2::ZERO,
+            interp: DemInterp::Bilinear,
+            fallback: 0.0,
+        };
+        Ok(dem.centered())
+    }
+
+    /// moves the raster so its middle sits on the world origin
+    pub fn centered(mut self) -> Self {
+        let extent = Vec2::new((self.cols - 1) as f32, (self.rows - 1) as f32);
+        self.origin = -extent * self.pixel_size * 0.5;
+        self
+    }
+
+    /// picks the reader from the extension (`.img`/`.lbl` or `.tif`/`.tiff`)
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, DemError> {
+        let path = path.as_ref();
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match ext.as_deref() {
+            Some("img" | "lbl") => Self::load_pds3(path),
+            Some("tif" | "tiff") => Self::load_geotiff(path),
+            _ => Err(DemError::Unsupported(format!(
+                "unknown extension on {}",
+                path.display()
+            ))),
+        }
+    }
+
+    /// PDS3 image with an attached label, or a detached `.lbl` next to it
+    pub fn load_pds3(path: impl AsRef<Path>) -> Result<Self, DemError> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)?;
+
+        let attached = bytes.starts_with(b"PDS_VERSION_ID") || bytes.starts_with(b"ODL_VERSION_ID");
+        let (label_text, label_path) = if attached {
+            (
+                String::from_utf8_lossy(&bytes).into_owned(),
+                path.to_path_buf(),
+            )
+        } else {
+            let lbl = find_detached_label(path)?;
+            (std::fs::read_to_string(&lbl)?, lbl)
+        };
+        let label = Label::parse(&label_text);
+
+        // ^IMAGE = 12 | 1024 <BYTES> | "X.IMG" | ("X.IMG", 12) | ("X.IMG", 1024 <BYTES>)
+        let pointer = label
+            .get("^IMAGE")
+            .ok_or_else(|| DemError::Label("no ^IMAGE pointer".into()))?;
+        let (file, start) = parse_pointer(pointer, label.size("RECORD_BYTES")?.unwrap_or(0))?;
+        let is_lbl = path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("lbl"));
+        let image_bytes = match file {
+            Some(name) => std::fs::read(resolve_sibling(&label_path, &name))?,
+            None if is_lbl => std::fs::read(path.with_extension("img"))?,
+            None => bytes,
+        };
+
+        let cols = label
+            .size("IMAGE.LINE_SAMPLES")?
+            .ok_or_else(|| DemError::Label("no LINE_SAMPLES".into()))?;
+        let rows = label
+            .size("IMAGE.LINES")?
+            .ok_or_else(|| DemError::Label("no LINES".into()))?;
+        let bits = label.size("IMAGE.SAMPLE_BITS")?.unwrap_or(16);
+        let kind = label.get("IMAGE.SAMPLE_TYPE").unwrap_or("MSB_INTEGER");
+        let prefix = label.size("IMAGE.LINE_PREFIX_BYTES")?.unwrap_or(0);
+        let suffix = label.size("IMAGE.LINE_SUFFIX_BYTES")?.unwrap_or(0);
+        let sample = SampleKind::from_pds(kind, bits)?;
+
+        let mut scale = label.float("IMAGE.SCALING_FACTOR").unwrap_or(1.0);
+        let mut offset = label.float("IMAGE.OFFSET").unwrap_or(0.0);
+        if label.get("IMAGE.UNIT").is_some_and(is_km) {
+            scale *= 1000.0;
+            offset *= 1000.0;
+        }
+        let nodata = label
+            .float("IMAGE.MISSING_CONSTANT")
+            .or_else(|| label.float("IMAGE.CORE_NULL"))
+            .or_else(|| label.float("IMAGE.NULL"));
+
+        // sizes straight from the label, so nothing here may wrap
+        let row_bytes = cols
+            .checked_mul(sample.bytes())
+            .and_then(|b| b.checked_add(prefix)?.checked_add(suffix));
+        let end = row_bytes.and_then(|b| rows.checked_mul(b)?.checked_add(start));
+        let (Some(row_bytes), Some(end)) = (row_bytes, end) else {
+            return Err(DemError::Label(format!(
+                "{cols}x{rows} image at byte {start} is too large"
+            )));
+        };
+        if image_bytes.len() < end {
+            return Err(DemError::Label(format!(
+                "image needs {end} bytes, file has {}",
+                image_bytes.len()
+            )));
+        }
+
+        let mut data = Vec::with_capacity(cols * rows);
+        for j in 0..rows {
+            let row = &image_bytes[start + j * row_bytes + prefix..];
+            for i in 0..cols {
+                let raw = sample.read(&row[i * sample.bytes()..]);
+                data.push(to_meters(raw, nodata, scale, offset));
+            }
+        }
+
+        let pixel_size = pds_pixel_size(&label)?;
+        Self::from_samples(cols, rows, data, pixel_size)
+    }
+
+    /// first band of a GeoTIFF, honouring GDAL nodata/scale/offset. a
+    /// ModelTiepoint places sample (0, 0) at its easting and minus its northing
+    /// (rows run south along +z), so tiles cut from one map line up; `centered`
+    /// brings a lone tile back to the origin. geographic rasters are unrolled
+    /// equirectangularly onto `EUROPA_RADIUS`, pixel-is-area half-pixel shifts
+    /// are ignored
+    pub fn load_geotiff(path: impl AsRef<Path>) -> Result<Self, DemError> {
+        let mut dec = Decoder::new(BufReader::new(File::open(path)?))?;
+        let (cols, rows) = dec.dimensions()?;
+        let (cols, rows) = (cols as usize, rows as usize);
+
+        let nodata = dec
+            .get_tag_ascii_string(Tag::GdalNodata)
+            .ok()
+            .and_then(|s| s.trim_matches(char::from(0)).trim().parse::<f64>().ok());
+        let (scale, offset) = dec
+            .get_tag_ascii_string(Tag::Unknown(42112)) // GDAL_METADATA
+            .map(|xml| gdal_scale_offset(&xml))
+            .unwrap_or((1.0, 0.0));
+
+        let pixel = dec.get_tag_f64_vec(Tag::ModelPixelScaleTag).ok();
+        let geographic = dec
+            .get_tag_u16_vec(Tag::GeoKeyDirectoryTag)
+            .is_ok_and(|keys| geokey(&keys, 1024) == Some(2)); // GTModelType 2 = geographic
+        // degrees on the sphere, meters otherwise
+        let to_m = |v: f64| {
+            if geographic {
+                v.to_radians() * EUROPA_RADIUS as f64
+            } else {
+                v
+            }
+        };
+        let pixel_size = match pixel.as_deref() {
+            Some([p, ..]) => to_m(*p) as f32,
+            _ => {
+                return Err(DemError::Unsupported(
+                    "GeoTIFF has no ModelPixelScale".into(),
+                ));
+            }
+        };
+        // (i, j, k, x, y, z): raster point (i, j) sits at map (x, y)
+        let tiepoint = dec
+            .get_tag_f64_vec(Tag::ModelTiepointTag)
+            .ok()
+            .filter(|t| t.len() >= 6)
+            .map(|t| {
+                let step = pixel_size as f64;
+                Vec2::new(
+                    (to_m(t[3]) - t[0] * step) as f32,
+                    -(to_m(t[4]) + t[1] * step) as f32,
+                )
+            });
+
+        let raw: Vec<f64> = match dec.read_image()? {
+            DecodingResult::U8(v) => v.into_iter().map(f64::from).collect(),
+            DecodingResult::U16(v) => v.into_iter().map(f64::from).collect(),
+            DecodingResult::U32(v) => v.into_iter().map(f64::from).collect(),
+            DecodingResult::U64(v) => v.into_iter().map(|s| s as f64).collect(),
+            DecodingResult::I8(v) => v.into_iter().map(f64::from).collect(),
+            DecodingResult::I16(v) => v.into_iter().map(f64::from).collect(),
+            DecodingResult::I32(v) => v.into_iter().map(f64::from).collect(),
+            DecodingResult::I64(v) => v.into_iter().map(|s| s as f64).collect(),
+            DecodingResult::F16(v) => v.into_iter().map(|s| s.to_f64()).collect(),
+            DecodingResult::F32(v) => v.into_iter().map(f64::from).collect(),
+            DecodingResult::F64(v) => v,
+        };
+
+        // interleaved bands: keep the first
+        let bands = (raw.len() / (cols * rows).max(1)).max(1);
+        let data = raw
+            .into_iter()
+            .step_by(bands)
+            .map(|s| to_meters(s, nodata, scale, offset))
+            .collect();
+
+        let mut dem = Self::from_samples(cols, rows, data, pixel_size)?;
+        if let Some(origin) = tiepoint {
+            dem.origin = origin;
+        }
+        Ok(dem)
+    }
+
+    fn sample(&self, i: isize, j: isize) -> f32 {
+        let i = i.clamp(0, self.cols as isize - 1) as usize;
+        let j = j.clamp(0, self.rows as isize - 1) as usize;
+        self.data[j * self.cols + i]
+    }
+
+    fn bilinear(&self, u: f32, v: f32) -> f32 {
+        let (i, j) = (u.floor(), v.floor());
+        let (fx, fz) = (u - i, v - j);
+        let (i, j) = (i as isize, j as isize);
+
+        // nan-aware: drop invalid corners and renormalise the remaining weights
+        let taps = [
+            (self.sample(i, j), (1.0 - fx) * (1.0 - fz)),
+            (self.sample(i + 1, j), fx * (1.0 - fz)),
+            (self.sample(i, j + 1), (1.0 - fx) * fz),
+            (self.sample(i + 1, j + 1), fx * fz),
+        ];
+        let (mut sum, mut w) = (0.0, 0.0);
+        for (h, k) in taps {
+            if h.is_finite() {
+                sum += h * k;
+                w += k;
+            }
+        }
+        if w > 1e-6 { sum / w } else { self.fallback }
+    }
+
+    fn bicubic(&self, u: f32, v: f32) -> f32 {
+        let (i, j) = (u.floor(), v.floor());
+        let (fx, fz) = (u - i, v - j);
+        let (i, j) = (i as isize, j as isize);
+
+        let mut rows = [0.0; 4];
+        for (r, dj) in (-1..=2).enumerate() {
+            let p = [-1, 0, 1, 2].map(|di| self.sample(i + di, j + dj));
+            if p.iter().any(|h| !h.is_finite()) {
+                return self.bilinear(u, v);
+            }
+            rows[r] = catmull_rom(p, fx);
+        }
+        catmull_rom(rows, fz)
+    }
+}
+
+impl HeightSource for Dem {
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        let uv = (p - self.origin.as_dvec2()) / self.pixel_size as f64;
+        let (u, v) = (uv.x as f32, uv.y as f32);
+
+        // half a pixel of grace past the edge, nothing beyond
+        let (w, h) = (self.cols as f32 - 1.0, self.rows as f32 - 1.0);
+        if u < -0.5 || v < -0.5 || u > w + 0.5 || v > h + 0.5 {
+            return self.fallback;
+        }
+        let (u, v) = (u.clamp(0.0, w), v.clamp(0.0, h));
+
+        match self.interp {
+            DemInterp::Bilinear => self.bilinear(u, v),
+            DemInterp::Bicubic => self.bicubic(u, v),
+        }
+    }
+}
+
+fn catmull_rom(p: [f32; 4], t: f32) -> f32 {
+    let a = -0.5 * p[0] + 1.5 * p[1] - 1.5 * p[2] + 0.5 * p[3];
+    let b = p[0] - 2.5 * p[1] + 2.0 * p[2] - 0.5 * p[3];
+    let c = -0.5 * p[0] + 0.5 * p[2];
+    ((a * t + b) * t + c) * t + p[1]
+}
+
+fn to_meters(raw: f64, nodata: Option<f64>, scale: f64, offset: f64) -> f32 {
+    // isis special pixels sit at the bottom of the f32 range
+    let special = raw <= -3.4e38 || !raw.is_finite();
+    let missing = nodata.is_some_and(|n| (raw - n).abs() <= n.abs() * 1e-6);
+    if special || missing {
+        f32::NAN
+    } else {
+        (raw * scale + offset) as f32
+    }
+}
+
+#[derive(Clone, Copy)]
+enum SampleKind {
+    U8,
+    I16 { msb: bool },
+    U16 { msb: bool },
+    I32 { msb: bool },
+    U32 { msb: bool },
+    F32 { msb: bool },
+    F64 { msb: bool },
+}
+
+impl SampleKind {
+    fn from_pds(kind: &str, bits: usize) -> Result<Self, DemError> {
+        let kind = kind.trim_matches('"').to_ascii_uppercase();
+        let msb = !(kind.starts_with("LSB") || kind.starts_with("PC") || kind.starts_with("VAX"));
+        let real = kind.ends_with("REAL") || kind == "FLOAT";
+        let unsigned = kind.contains("UNSIGNED");
+
+        Ok(match (real, unsigned, bits) {
+            (false, _, 8) => SampleKind::U8,
+            (false, false, 16) => SampleKind::I16 { msb },
+            (false, true, 16) => SampleKind::U16 { msb },
+            (false, false, 32) => SampleKind::I32 { msb },
+            (false, true, 32) => SampleKind::U32 { msb },
+            (true, _, 32) => SampleKind::F32 { msb },
+            (true, _, 64) => SampleKind::F64 { msb },
+            _ => {
+                return Err(DemError::Unsupported(format!(
+                    "SAMPLE_TYPE {kind} with {bits} bits"
+                )));
+            }
+        })
+    }
+
+    fn bytes(&self) -> usize {
+        match self {
+            SampleKind::U8 => 1,
+            SampleKind::I16 { .. } | SampleKind::U16 { .. } => 2,
+            SampleKind::I32 { .. } | SampleKind::U32 { .. } | SampleKind::F32 { .. } => 4,
+            SampleKind::F64 { .. } => 8,
+        }
+    }
+
+    fn read(&self, b: &[u8]) -> f64 {
+        macro_rules! num {
+            ($t:ty, $n:literal, $msb:expr) => {{
+                let a: [u8; $n] = b[..$n].try_into().unwrap();
+                if $msb {
+                    <$t>::from_be_bytes(a)
+                } else {
+                    <$t>::from_le_bytes(a)
+                }
+            }};
+        }
+        match *self {
+            SampleKind::U8 => b[0] as f64,
+            SampleKind::I16 { msb } => num!(i16, 2, msb) as f64,
+            SampleKind::U16 { msb } => num!(u16, 2, msb) as f64,
+            SampleKind::I32 { msb } => num!(i32, 4, msb) as f64,
+            SampleKind::U32 { msb } => num!(u32, 4, msb) as f64,
+            SampleKind::F32 { msb } => num!(f32, 4, msb) as f64,
+            SampleKind::F64 { msb } => num!(f64, 8, msb),
+        }
+    }
+}
+
+/// flat `KEY = VALUE` view of an ODL label; keys inside objects are prefixed
+/// with the object name (`IMAGE.LINES`)
+struct Label {
+    values: HashMap<String, String>,
+}
+
+impl Label {
+    fn parse(text: &str) -> Self {
+        let mut values = HashMap::new();
+        let mut objects: Vec<String> = Vec::new();
+        let mut lines = text.lines();
+
+        while let Some(line) = lines.next() {
+            let line = line.split("/*").next().unwrap_or("").trim();
+            if line == "END" {
+                break;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let key = key.trim().to_ascii_uppercase();
+            let mut value = value.trim().to_string();
+
+            // parenthesised lists can wrap over several lines
+            if value.starts_with('(') {
+                while !value.contains(')') {
+                    let Some(more) = lines.next() else { break };
+                    value.push(' ');
+                    value.push_str(more.trim());
+                }
+            }
+
+            match key.as_str() {
+                "OBJECT" | "GROUP" => objects.push(value.to_ascii_uppercase()),
+                "END_OBJECT" | "END_GROUP" => {
+                    objects.pop();
+                }
+                _ => {
+                    // map projection keys are looked up by bare name
+                    let full = match objects.last() {
+                        Some(obj) if obj == "IMAGE" => format!("IMAGE.{key}"),
+                        _ => key,
+                    };
+                    values.entry(full).or_insert(value);
+                }
+            }
+        }
+        Self { values }
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(String::as_str)
+    }
+
+    /// leading number of a value, units (`<METERS>`) and quotes stripped
+    fn float(&self, key: &str) -> Option<f64> {
+        let v = self.get(key)?.trim_matches('"');
+        let v = v.split(['<', ' ']).next()?.trim();
+        // PDS allows radix literals like 16#FF7FFFFB#
+        if let Some(hex) = v.strip_prefix("16#").and_then(|h| h.strip_suffix('#')) {
+            return u32::from_str_radix(hex, 16)
+                .ok()
+                .map(|b| f32::from_bits(b) as f64);
+        }
+        v.parse().ok()
+    }
+
+    fn int(&self, key: &str) -> Option<i64> {
+        self.float(key).map(|f| f as i64)
+    }
+
+    /// a count or byte size, `DemError::Label` if it's negative
+    fn size(&self, key: &str) -> Result<Option<usize>, DemError> {
+        self.int(key)
+            .map(|v| usize::try_from(v).map_err(|_| DemError::Label(format!("bad {key} {v}"))))
+            .transpose()
+    }
+}
+
+fn is_km(unit: &str) -> bool {
+    let u = unit.trim_matches('"').to_ascii_uppercase();
+    u.starts_with("KILOMET") || u == "KM"
+}
+
+/// (data file if named, byte offset)
+fn parse_pointer(p: &str, record_bytes: usize) -> Result<(Option<String>, usize), DemError> {
+    let p = p.trim().trim_start_matches('(').trim_end_matches(')');
+    let mut parts = p.split(',').map(str::trim);
+    let first = parts.next().unwrap_or("");
+
+    let (file, loc) = if first.starts_with('"') {
+        (Some(first.trim_matches('"').to_string()), parts.next())
+    } else {
+        (None, Some(first))
+    };
+
+    let offset = match loc {
+        None => 0,
+        Some(loc) if loc.contains("<BYTES>") => {
+            let b: usize = loc
+                .split('<')
+                .next()
+                .unwrap_or("")
+                .trim()
+                .parse()
+                .map_err(|_| DemError::Label(format!("bad ^IMAGE pointer {p}")))?;
+            b.saturating_sub(1)
+        }
+        Some(loc) => {
+            let r: usize = loc
+                .parse()
+                .map_err(|_| DemError::Label(format!("bad ^IMAGE pointer {p}")))?;
+            r.saturating_sub(1)
+                .checked_mul(record_bytes)
+                .ok_or_else(|| DemError::Label(format!("bad ^IMAGE pointer {p}")))?
+        }
+    };
+    Ok((file, offset))
+}
+
+fn find_detached_label(img: &Path) -> Result<PathBuf, DemError> {
+    for ext in ["lbl", "LBL"] {
+        let lbl = img.with_extension(ext);
+        if lbl.exists() {
+            return Ok(lbl);
+        }
+    }
+    Err(DemError::Label(format!(
+        "{} has no attached label and no .lbl next to it",
+        img.display()
+    )))
+}
+
+/// data file named in a label, matched case-insensitively next to the label
+fn resolve_sibling(label: &Path, name: &str) -> PathBuf {
+    let dir = label.parent().unwrap_or(Path::new("."));
+    let direct = dir.join(name);
+    if direct.exists() {
+        return direct;
+    }
+    std::fs::read_dir(dir)
+        .ok()
+        .and_then(|entries| {
+            entries
+                .flatten()
+                .find(|e| e.file_name().to_string_lossy().eq_ignore_ascii_case(name))
+        })
+        .map(|e| e.path())
+        .unwrap_or(direct)
+}
+
+fn pds_pixel_size(label: &Label) -> Result<f32, DemError> {
+    if let Some(scale) = label.float("MAP_SCALE") {
+        let per_km = label
+            .get("MAP_SCALE")
+            .is_some_and(|v| v.to_ascii_uppercase().contains("<KM"));
+        return Ok(if per_km { scale * 1000.0 } else { scale } as f32);
+    }
+    // pixels per degree
+    if let Some(res) = label.float("MAP_RESOLUTION") {
+        let m_per_deg = (EUROPA_RADIUS as f64) * std::f64::consts::PI / 180.0;
+        return Ok((m_per_deg / res) as f32);
+    }
+    Err(DemError::Label("no MAP_SCALE or MAP_RESOLUTION".into()))
+}
+
+/// value of a GeoKey from the GeoKeyDirectory (only inline short values)
+fn geokey(dir: &[u16], id: u16) -> Option<u16> {
+    dir.chunks_exact(4)
+        .skip(1)
+        .find(|k| k[0] == id && k[1] == 0)
+        .map(|k| k[3])
+}
+
+/// SCALE/OFFSET items out of GDAL's metadata xml
+fn gdal_scale_offset(xml: &str) -> (f64, f64) {
+    let item = |name: &str| {
+        let tag = format!("name=\"{name}\"");
+        let at = xml.find(&tag)?;
+        let rest = &xml[at..];
+        let start = rest.find('>')? + 1;
+        let end = rest.find("</Item>")?;
+        rest[start..end].trim().parse::<f64>().ok()
+    };
+    (item("SCALE").unwrap_or(1.0), item("OFFSET").unwrap_or(0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_attached_pds3_label() {
+        let label = "PDS_VERSION_ID = PDS3\r\n\
+            RECORD_TYPE = FIXED_LENGTH\r\n\
+            RECORD_BYTES = 512\r\n\
+            ^IMAGE = 2\r\n\
+            OBJECT = IMAGE\r\n\
+              LINES = 2\r\n\
+              LINE_SAMPLES = 3\r\n\
+              SAMPLE_TYPE = LSB_INTEGER\r\n\
+              SAMPLE_BITS = 16\r\n\
+              SCALING_FACTOR = 0.5\r\n\
+              OFFSET = -10.0\r\n\
+              MISSING_CONSTANT = -32768\r\n\
+            END_OBJECT = IMAGE\r\n\
+            OBJECT = IMAGE_MAP_PROJECTION\r\n\
+              MAP_SCALE = 0.2 <KM/PIXEL>\r\n\
+            END_OBJECT = IMAGE_MAP_PROJECTION\r\n\
+            END\r\n";
+        let mut bytes = label.as_bytes().to_vec();
+        bytes.resize(512, b' ');
+        for v in [20i16, 40, -32768, 0, 2, 4] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+
+        let path = std::env::temp_dir().join("europa_dem_attached.img");
+        std::fs::write(&path, bytes).unwrap();
+        let dem = Dem::load(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!((dem.cols, dem.rows), (3, 2));
+        assert_eq!(dem.pixel_size, 200.0);
+        assert_eq!(dem.data[0], 0.0);
+        assert_eq!(dem.data[1], 10.0);
+        assert!(dem.data[2].is_nan());
+
+        // between the first two samples, and next to the hole only valid taps count
+        let o = dem.origin;
+        assert!((dem.height_at(o.x + 100.0, o.y) - 5.0).abs() < 1e-4);
+        assert!((dem.height_at(o.x + 300.0, o.y) - 10.0).abs() < 1e-4);
+    }
+
+    fn write_attached_km_label(path: &Path) {
+        let label = "PDS_VERSION_ID = PDS3\r\n\
+            RECORD_BYTES = 256\r\n\
+            ^IMAGE = 2\r\n\
+            OBJECT = IMAGE\r\n\
+              LINES = 1\r\n\
+              LINE_SAMPLES = 2\r\n\
+              SAMPLE_TYPE = PC_REAL\r\n\
+              SAMPLE_BITS = 32\r\n\
+              UNIT = KILOMETER\r\n\
+              SCALING_FACTOR = 1.0\r\n\
+              OFFSET = 1560.8\r\n\
+            END_OBJECT = IMAGE\r\n\
+            MAP_SCALE = 100.0 <METERS/PIXEL>\r\n\
+            END\r\n";
+        let mut bytes = label.as_bytes().to_vec();
+        bytes.resize(256, b' ');
+        for v in [0.5f32, -0.25] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn km_offset_is_scaled_with_the_samples() {
+        let path = std::env::temp_dir().join("europa_dem_km.img");
+        write_attached_km_label(&path);
+        let dem = Dem::load(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(dem.pixel_size, 100.0);
+        assert!((dem.data[0] - 1_561_300.0).abs() < 0.5, "{}", dem.data[0]);
+        assert!((dem.data[1] - 1_560_550.0).abs() < 0.5, "{}", dem.data[1]);
+    }
+
+    #[test]
+    fn reads_geotiff_with_gdal_metadata() {
+        use tiff::encoder::{TiffEncoder, colortype::Gray16};
+
+        let path = std::env::temp_dir().join("europa_dem_gdal.tif");
+        {
+            let mut enc = TiffEncoder::new(File::create(&path).unwrap()).unwrap();
+            let mut image = enc.new_image::<Gray16>(3, 2).unwrap();
+            let dir = image.encoder();
+            dir.write_tag(Tag::ModelPixelScaleTag, &[25.0, 25.0, 0.0][..])
+                .unwrap();
+            dir.write_tag(
+                Tag::ModelTiepointTag,
+                &[1.0, 0.0, 0.0, 5000.0, 2000.0, 0.0][..],
+            )
+            .unwrap();
+            dir.write_tag(Tag::GdalNodata, "65535").unwrap();
+            let meta = "<GDALMetadata><Item name=\"SCALE\" sample=\"0\">0.5</Item>\
+                <Item name=\"OFFSET\" sample=\"0\">-100</Item></GDALMetadata>";
+            dir.write_tag(Tag::Unknown(42112), meta).unwrap();
+            image.write_data(&[200, 210, 220, 65535, 240, 250]).unwrap();
+        }
+        let dem = Dem::load(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!((dem.cols, dem.rows), (3, 2));
+        assert_eq!(dem.pixel_size, 25.0);
+        assert_eq!(&dem.data[..3], &[0.0, 5.0, 10.0]);
+        assert!(dem.data[3].is_nan());
+        assert_eq!(dem.data[5], 25.0);
+        // pixel (1, 0) is at easting 5000, northing 2000
+        assert_eq!(dem.origin, Vec2::new(4975.0, -2000.0));
+        assert_eq!(dem.height_at(5000.0, -2000.0), 5.0);
+        assert_eq!(dem.centered().origin, Vec2::new(-25.0, -12.5));
+    }
+
+    #[test]
+    fn malformed_pds3_labels_are_errors() {
+        let path = std::env::temp_dir().join("europa_dem_malformed.img");
+        let try_label = |image: &str| {
+            let label = format!(
+                "PDS_VERSION_ID = PDS3\r\n\
+                 RECORD_BYTES = 256\r\n\
+                 ^IMAGE = 2\r\n\
+                 OBJECT = IMAGE\r\n{image}\
+                   SAMPLE_TYPE = LSB_INTEGER\r\n\
+                   SAMPLE_BITS = 16\r\n\
+                 END_OBJECT = IMAGE\r\n\
+                 MAP_SCALE = 100.0 <METERS/PIXEL>\r\n\
+                 END\r\n"
+            );
+            let mut bytes = label.into_bytes();
+            bytes.resize(256 + 8, 0);
+            std::fs::write(&path, bytes).unwrap();
+            Dem::load(&path)
+        };
+
+        assert!(try_label("LINES = 2\r\nLINE_SAMPLES = 2\r\n").is_ok());
+        for image in [
+            "LINES = -2\r\nLINE_SAMPLES = 2\r\n",
+            "LINES = 2\r\nLINE_SAMPLES = 9000000000000000000\r\n",
+            "LINES = 4611686018427387904\r\nLINE_SAMPLES = 2\r\n",
+            "LINES = 2\r\nLINE_SAMPLES = 2\r\nLINE_PREFIX_BYTES = -1\r\n",
+            "LINES = 2\r\nLINE_SAMPLES = 2\r\nLINE_SUFFIX_BYTES = 2\r\n",
+        ] {
+            assert!(
+                matches!(try_label(image), Err(DemError::Label(_))),
+                "{image:?} was accepted"
+            );
+        }
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn geotiff_needs_a_usable_pixel_scale() {
+        use tiff::encoder::{TiffEncoder, colortype::Gray16};
+
+        let path = std::env::temp_dir().join("europa_dem_scale.tif");
+        for scale in [
+            &[][..],
+            &[0.0, 0.0, 0.0][..],
+            &[-5.0, 5.0, 0.0][..],
+            &[f64::NAN][..],
+        ] {
+            {
+                let mut enc = TiffEncoder::new(File::create(&path).unwrap()).unwrap();
+                let mut image = enc.new_image::<Gray16>(2, 2).unwrap();
+                image
+                    .encoder()
+                    .write_tag(Tag::ModelPixelScaleTag, scale)
+                    .unwrap();
+                image.write_data(&[1, 2, 3, 4]).unwrap();
+            }
+            assert!(
+                Dem::load(&path).is_err(),
+                "pixel scale {scale:?} was accepted"
+            );
+        }
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn rejects_rasters_that_dont_add_up() {
+        assert!(Dem::from_samples(0, 4, vec![], 1.0).is_err());
+        assert!(Dem::from_samples(2, 2, vec![0.0; 4], 0.0).is_err());
+        assert!(Dem::from_samples(2, 2, vec![0.0; 4], f32::NAN).is_err());
+        assert!(Dem::from_samples(3, 0, vec![], 1.0).is_err());
+        assert!(Dem::from_samples(3, 3, vec![0.0; 8], 1.0).is_err());
+    }
+
+    #[test]
+    fn bicubic_reproduces_a_plane_and_skips_holes() {
+        let (cols, rows) = (6, 5);
+        let plane = |u: f32, v: f32| 2.0 * u - 3.0 * v + 1.0;
+        let data = (0..cols * rows)
+            .map(|k| plane((k % cols) as f32, (k / cols) as f32))
+            .collect();
+        let mut dem = Dem::from_samples(cols, rows, data, 10.0).unwrap();
+        dem.interp = DemInterp::Bicubic;
+        let o = dem.origin;
+        for (u, v) in [(1.25, 1.5), (2.0, 2.0), (3.7, 2.2)] {
+            let h = dem.height_at(o.x + u * 10.0, o.y + v * 10.0);
+            assert!((h - plane(u, v)).abs() < 1e-4, "{h} at ({u}, {v})");
+        }
+
+        // a hole in the 4x4 footprint drops that point back to bilinear
+        dem.data[2 * cols + 4] = f32::NAN;
+        let (u, v) = (2.5, 2.5);
+        let bicubic = dem.bicubic(u, v);
+        assert_eq!(bicubic, dem.bilinear(u, v));
+        assert!((bicubic - plane(u, v)).abs() < 1e-4);
+    }
+}
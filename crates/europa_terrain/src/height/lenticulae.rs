@@ -0,0 +1,206 @@
+use bevy::math::DVec2;
+use bevy::prelude::*;
+use europa_math::{HashRng, hash_cell, smoothstep};
+use noise::NoiseFn;
+use serde::{Deserialize, Serialize};
+
+use super::HeightSource;
+use super::noise::Perlin;
+
+/// how far the noise may push a chaos spot's rim in or out, in radii
+const RAGGED: f32 = 0.075;
+
+/// radial falloff of a dome or pit, 1 at the centre and 0 at the edge
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum RadialProfile {
+    Gaussian,
+    Cosine,
+    /// flat top out to `flat` of the radius, then a smooth shoulder
+    Plateau {
+        flat: f32,
+    },
+}
+
+impl RadialProfile {
+    pub fn at(self, r: f32) -> f32 {
+        if r >= 1.0 {
+            return 0.0;
+        }
+        match self {
+            RadialProfile::Gaussian => {
+                let e = (-4.0_f32).exp();
+                ((-4.0 * r * r).exp() - e) / (1.0 - e)
+            }
+            RadialProfile::Cosine => 0.5 * (1.0 + (std::f32::consts::PI * r).cos()),
+            RadialProfile::Plateau { flat } => 1.0 - smoothstep(flat, 1.0, r),
+        }
+    }
+}
+
+/// lenticulae: the 5–20 km domes, pits and small chaos spots freckling the
+/// ridged plains. heights are relative, 0 away from any feature
+pub struct Lenticulae {
+    pub seed: u32,
+    /// noise for the chaos spot interiors
+    pub perlin: Perlin,
+    /// features per km²
+    pub density: f32,
+    /// meters
+    pub diameter_min: f32,
+    pub diameter_max: f32,
+    /// relative population of each kind, need not sum to 1
+    pub dome_weight: f32,
+    pub pit_weight: f32,
+    pub chaos_weight: f32,
+    /// relief at the centre, meters
+    pub dome_height: f32,
+    pub pit_depth: f32,
+    pub chaos_depth: f32,
+    pub dome_profile: RadialProfile,
+    pub pit_profile: RadialProfile,
+    /// hummocks inside chaos spots
+    pub chaos_roughness: f32,
+    pub chaos_freq: f32,
+    /// largest long/short axis ratio
+    pub max_elongation: f32,
+}
+
+impl Lenticulae {
+    pub fn europa(seed: u32) -> Self {
+        Self {
+            seed,
+            perlin: Perlin::new(seed ^ 0x7f4a_7c15),
+            density: 0.004,
+            diameter_min: 5000.0,
+            diameter_max: 20000.0,
+            dome_weight: 0.5,
+            pit_weight: 0.3,
+            chaos_weight: 0.2,
+            dome_height: 8.0,
+            pit_depth: 5.0,
+            chaos_depth: 3.0,
+            dome_profile: RadialProfile::Gaussian,
+            pit_profile: RadialProfile::Cosine,
+            chaos_roughness: 2.0,
+            chaos_freq: 1.0 / 150.0,
+            max_elongation: 1.4,
+        }
+    }
+
+    fn chaos(&self, p: DVec2, r: f32, depth: f32) -> f32 {
+        let mut a = 1.0;
+        let mut sum = 0.0;
+        let mut amp = 0.0;
+        let mut f = p * self.chaos_freq as f64;
+        for _ in 0..3 {
+            sum += a * (self.perlin.get(f.to_array()) as f32).abs();
+            amp += a;
+            f *= 2.0;
+            a *= 0.5;
+        }
+        // ragged margin: the noise eats into the rim, or pushes it out by up to `RAGGED`
+        let mask = RadialProfile::Plateau { flat: 0.55 }.at(r + 2.0 * RAGGED * (sum / amp - 0.5));
+        (-depth + self.chaos_roughness * sum / amp) * mask
+    }
+}
+
+impl HeightSource for Lenticulae {
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        // a feature's footprint never reaches further than its stretched, ragged radius
+        let cell = self.diameter_max * 0.5 * self.max_elongation.max(1.0) * (1.0 + RAGGED);
+        let lambda = self.density * 1e-6 * cell * cell;
+        let total = self.dome_weight + self.pit_weight + self.chaos_weight;
+        let cx = (p.x / cell as f64).floor() as i32;
+        let cz = (p.y / cell as f64).floor() as i32;
+
+        let mut sum = 0.0;
+        for dz in -1..=1 {
+            for dx in -1..=1 {
+                let (ix, iz) = (cx + dx, cz + dz);
+                let mut rng = HashRng(hash_cell(self.seed, ix, iz));
+                for _ in 0..rng.poisson(lambda) {
+                    let jitter = DVec2::new(rng.next_f32().into(), rng.next_f32().into());
+                    let centre = (IVec2::new(ix, iz).as_dvec2() + jitter) * cell as f64;
+                    let radius = 0.5 * rng.range(self.diameter_min, self.diameter_max);
+                    let stretch = rng.range(1.0, self.max_elongation.max(1.0));
+                    let axis = Vec2::from_angle(rng.range(0.0, std::f32::consts::PI));
+                    let kind = rng.next_f32() * total;
+                    let size = rng.range(0.6, 1.0);
+
+                    let rel = (p - centre).as_vec2();
+                    let along = rel.dot(axis) / stretch;
+                    let across = rel.dot(axis.perp());
+                    let r = Vec2::new(along, across).length() / radius;
+                    if r >= 1.0 + RAGGED {
+                        continue;
+                    }
+
+                    sum += if kind < self.dome_weight {
+                        self.dome_height * size * self.dome_profile.at(r)
+                    } else if kind < self.dome_weight + self.pit_weight {
+                        -self.pit_depth * size * self.pit_profile.at(r)
+                    } else {
+                        self.chaos(p, r, self.chaos_depth * size)
+                    };
+                }
+            }
+        }
+        sum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// walks `l` along z = `z` in 0.25 m steps, returns the largest step and the lowest height
+    fn walk(l: &Lenticulae, z: f32) -> (f32, f32) {
+        let mut prev = l.height_at(-20_000.0, z);
+        let (mut jump, mut low) = (0.0_f32, prev);
+        for k in 1..160_000 {
+            let h = l.height_at(-20_000.0 + k as f32 * 0.25, z);
+            jump = jump.max((h - prev).abs());
+            low = low.min(h);
+            prev = h;
+        }
+        (jump, low)
+    }
+
+    #[test]
+    fn ragged_rim_ends_at_the_cut_off() {
+        let l = Lenticulae::europa(5);
+        for k in 0..2000 {
+            let p = DVec2::new(k as f64 * 7.3, k as f64 * -3.1);
+            assert_eq!(l.chaos(p, 1.0 + RAGGED, 3.0), 0.0, "rim left open at {p}");
+        }
+    }
+
+    #[test]
+    fn chaos_spots_have_no_rim_step() {
+        // smooth chaos spots only, so a step at the ragged rim stands out
+        let l = Lenticulae {
+            density: 0.02,
+            dome_weight: 0.0,
+            pit_weight: 0.0,
+            chaos_roughness: 0.0,
+            ..Lenticulae::europa(5)
+        };
+        for z in [0.0, 3_100.0, -7_700.0] {
+            let (jump, low) = walk(&l, z);
+            assert!(low < -1.0, "walk at z {z} missed the spots");
+            assert!(jump < 0.06, "step of {jump} along z {z}");
+        }
+    }
+
+    #[test]
+    fn domes_and_pits_meet_the_plains() {
+        let l = Lenticulae {
+            density: 0.02,
+            chaos_weight: 0.0,
+            ..Lenticulae::europa(5)
+        };
+        let (jump, low) = walk(&l, 1_500.0);
+        assert!(low < -1.0);
+        assert!(jump < 0.01, "step of {jump}");
+    }
+}
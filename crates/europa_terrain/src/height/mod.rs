@@ -1,16 +1,229 @@
 use std::sync::Arc;
 
-/// continuous height function on the XZ plane
+use bevy::math::{DVec2, Vec2};
+
+/// step for the finite-difference gradient fallback, meters
+pub const GRADIENT_EPS: f32 = 0.05;
+
+/// continuous height function on the XZ plane, implemented on double precision
+/// positions so terrain stays put thousands of km from the start
 pub trait HeightSource: Send + Sync + 'static {
-    fn height_at(&self, x: f32, z: f32) -> f32;
+    /// height at world xz. keep positions in f64 until they are small offsets
+    /// (from a lattice site, a crater centre), and pass them on that way
+    fn height_at_world(&self, p: DVec2) -> f32;
+
+    /// `height_at_world` from a single precision position
+    fn height_at(&self, x: f32, z: f32) -> f32 {
+        self.height_at_world(world(x, z))
+    }
+
+    /// height and (dh/dx, dh/dz). central differences unless the source
+    /// knows its derivative
+    fn height_and_gradient(&self, x: f32, z: f32) -> (f32, Vec2) {
+        self.height_and_gradient_world(world(x, z))
+    }
+
+    /// `height_and_gradient` from a double precision position
+    fn height_and_gradient_world(&self, p: DVec2) -> (f32, Vec2) {
+        let e = GRADIENT_EPS as f64;
+        let (ex, ez) = (DVec2::new(e, 0.0), DVec2::new(0.0, e));
+        let dx = self.height_at_world(p + ex) - self.height_at_world(p - ex);
+        let dz = self.height_at_world(p + ez) - self.height_at_world(p - ez);
+        (
+            self.height_at_world(p),
+            Vec2::new(dx, dz) / (2.0 * GRADIENT_EPS),
+        )
+    }
+
+    /// heights of an `nx` x `nz` grid starting at `origin`, row-major with x
+    /// fastest, into `out[..nx * nz]`. sample `(i, j)` sits at
+    /// `origin + (i, j) * spacing` and matches `height_at_world` there
+    fn sample_grid(&self, origin: DVec2, spacing: f32, nx: usize, nz: usize, out: &mut [f32]) {
+        self.sample_points(&grid_points(origin, spacing, nx, nz), out);
+    }
+
+    /// `sample_grid` plus the gradient at every sample
+    fn sample_grid_with_gradient(
+        &self,
+        origin: DVec2,
+        spacing: f32,
+        nx: usize,
+        nz: usize,
+        out: &mut [f32],
+        grad: &mut [Vec2],
+    ) {
+        self.sample_points_with_gradient(&grid_points(origin, spacing, nx, nz), out, grad);
+    }
+
+    /// heights at scattered `points` into `out[..points.len()]`, matching
+    /// `height_at_world`. grids land here too, and warps and projections pass
+    /// their moved points on, so batched noise sees the whole set at once
+    fn sample_points(&self, points: &[DVec2], out: &mut [f32]) {
+        for (h, &p) in out.iter_mut().zip(points) {
+            *h = self.height_at_world(p);
+        }
+    }
+
+    /// `sample_points` plus the gradient at every point
+    fn sample_points_with_gradient(&self, points: &[DVec2], out: &mut [f32], grad: &mut [Vec2]) {
+        for ((h, g), &p) in out.iter_mut().zip(grad).zip(points) {
+            (*h, *g) = self.height_and_gradient_world(p);
+        }
+    }
+}
+
+fn world(x: f32, z: f32) -> DVec2 {
+    DVec2::new(x.into(), z.into())
+}
+
+/// world xz of the `k`th sample of a row-major grid
+pub fn grid_point(origin: DVec2, spacing: f32, nx: usize, k: usize) -> DVec2 {
+    let (i, j) = (k % nx, k / nx);
+    let spacing = spacing as f64;
+    origin + DVec2::new(i as f64 * spacing, j as f64 * spacing)
+}
+
+/// every sample of an `nx` x `nz` grid, in `sample_grid` order
+pub fn grid_points(origin: DVec2, spacing: f32, nx: usize, nz: usize) -> Vec<DVec2> {
+    (0..nx * nz)
+        .map(|k| grid_point(origin, spacing, nx, k))
+        .collect()
 }
 
 pub type HeightFn = Arc<dyn HeightSource>;
 
+pub mod bands;
+pub mod chaos;
 pub mod comb;
+pub mod craters;
+pub mod curvature;
+pub mod dem;
+pub mod lenticulae;
 pub mod noise;
+pub mod recipe;
+pub mod ridges;
 pub mod warp;
 
 pub fn arc<S: HeightSource>(s: S) -> HeightFn {
     Arc::new(s)
 }
+
+impl HeightSource for HeightFn {
+    fn height_at(&self, x: f32, z: f32) -> f32 {
+        self.as_ref().height_at(x, z)
+    }
+
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        self.as_ref().height_at_world(p)
+    }
+
+    fn height_and_gradient(&self, x: f32, z: f32) -> (f32, Vec2) {
+        self.as_ref().height_and_gradient(x, z)
+    }
+
+    fn height_and_gradient_world(&self, p: DVec2) -> (f32, Vec2) {
+        self.as_ref().height_and_gradient_world(p)
+    }
+
+    fn sample_grid(&self, origin: DVec2, spacing: f32, nx: usize, nz: usize, out: &mut [f32]) {
+        self.as_ref().sample_grid(origin, spacing, nx, nz, out)
+    }
+
+    fn sample_grid_with_gradient(
+        &self,
+        origin: DVec2,
+        spacing: f32,
+        nx: usize,
+        nz: usize,
+        out: &mut [f32],
+        grad: &mut [Vec2],
+    ) {
+        self.as_ref()
+            .sample_grid_with_gradient(origin, spacing, nx, nz, out, grad)
+    }
+
+    fn sample_points(&self, points: &[DVec2], out: &mut [f32]) {
+        self.as_ref().sample_points(points, out)
+    }
+
+    fn sample_points_with_gradient(&self, points: &[DVec2], out: &mut [f32], grad: &mut [Vec2]) {
+        self.as_ref().sample_points_with_gradient(points, out, grad)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::{EUROPA_RADIUS, TerrainParams};
+    use ::noise::NoiseFn;
+
+    #[test]
+    fn perlin_matches_noise_crate() {
+        let ours = noise::Perlin::new(42);
+        let theirs = ::noise::Perlin::new(42);
+        for p in [[0.3, 0.7], [-12.25, 5.5], [103.9, -88.1]] {
+            assert_eq!(ours.get(p), theirs.get(p));
+            assert!((ours.value_and_gradient(p[0], p[1]).0 - theirs.get(p)).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn analytic_gradients_match_finite_differences() {
+        let h = crate::TerrainPlugin::europa_default().height;
+        let e = 0.01;
+        for (x, z) in [(0.0, 0.0), (120.5, -40.25), (-900.0, 1300.0), (17.0, 2.0)] {
+            let (v, g) = h.height_and_gradient(x, z);
+            let fd = Vec2::new(
+                h.height_at(x + e, z) - h.height_at(x - e, z),
+                h.height_at(x, z + e) - h.height_at(x, z - e),
+            ) / (2.0 * e);
+            assert!((v - h.height_at(x, z)).abs() < 1e-5);
+            assert!(
+                (g - fd).length() < 1e-2 * (1.0 + fd.length()),
+                "gradient {g} vs finite difference {fd} at ({x}, {z})"
+            );
+        }
+    }
+
+    #[test]
+    fn curved_surface_drops_by_the_sagitta() {
+        let flat = crate::TerrainPlugin::europa_default().height;
+        let params = TerrainParams {
+            curvature: Some(EUROPA_RADIUS),
+            ..TerrainParams::europa_demo()
+        };
+        let curved = params.surface(flat.clone());
+        // ~2.9 m down 3 km out, and the gradient still matches
+        let drop = flat.height_at(3000.0, 0.0) - curved.height_at(3000.0, 0.0);
+        assert!((drop - 3000.0_f32.powi(2) / (2.0 * EUROPA_RADIUS)).abs() < 0.01);
+        let e = 0.05;
+        for (x, z) in [(0.0, 0.0), (2500.0, -1300.0)] {
+            let (_, g) = curved.height_and_gradient(x, z);
+            let fd = Vec2::new(
+                curved.height_at(x + e, z) - curved.height_at(x - e, z),
+                curved.height_at(x, z + e) - curved.height_at(x, z - e),
+            ) / (2.0 * e);
+            assert!(
+                (g - fd).length() < 1e-2 * (1.0 + fd.length()),
+                "{g} vs {fd}"
+            );
+        }
+    }
+
+    #[test]
+    fn sample_grid_matches_pointwise() {
+        let h = crate::TerrainPlugin::europa_default().height;
+        let (origin, spacing, nx, nz) = (DVec2::new(-37.5, 210.0), 3.25, 7, 5);
+        let mut out = vec![0.0; nx * nz];
+        let mut with_grad = vec![0.0; nx * nz];
+        let mut grad = vec![Vec2::ZERO; nx * nz];
+        h.sample_grid(origin, spacing, nx, nz, &mut out);
+        h.sample_grid_with_gradient(origin, spacing, nx, nz, &mut with_grad, &mut grad);
+        for k in 0..nx * nz {
+            let p = grid_point(origin, spacing, nx, k);
+            let (v, g) = h.height_and_gradient_world(p);
+            assert_eq!(out[k], h.height_at_world(p));
+            assert!((with_grad[k] - v).abs() < 1e-5 && (grad[k] - g).length() < 1e-5);
+        }
+    }
+}
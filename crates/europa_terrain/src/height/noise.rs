@@ -1,8 +1,221 @@
+use bevy::math::DVec2;
+use bevy::prelude::*;
+use europa_math::{HashRng, hash_cell};
+pub(crate) use noise::NoiseFn;
+use noise::permutationtable::{NoiseHasher, PermutationTable};
+use serde::{Deserialize, Serialize};
+
 use super::HeightSource;
-pub(crate) use noise::{NoiseFn, Perlin};
 
-pub struct PerlinFbm {
-    pub perlin: Perlin,
+/// 2D perlin noise, bit-for-bit `noise::Perlin`, that can also return its
+/// derivative. keeps its own copy of the permutation table since the crate's is private
+#[derive(Clone, Copy)]
+pub struct Perlin {
+    table: PermutationTable,
+}
+
+impl Perlin {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            table: PermutationTable::new(seed),
+        }
+    }
+
+    /// value and d/dx, d/dz in noise space
+    pub fn value_and_gradient(&self, x: f64, z: f64) -> (f64, [f64; 2]) {
+        const SCALE_FACTOR: f64 = 2.0 / std::f64::consts::SQRT_2;
+
+        let (cx, cz) = (x.floor(), z.floor());
+        let (fx, fz) = (x - cx, z - cz);
+        let (cx, cz) = (cx as isize, cz as isize);
+
+        // corner gradient (gx, gz) and its dot with the offset to that corner
+        let corner = |i: isize, j: isize| {
+            let (gx, gz) = match self.table.hash(&[cx + i, cz + j]) & 0b11 {
+                0 => (1.0, 1.0),
+                1 => (-1.0, 1.0),
+                2 => (1.0, -1.0),
+                _ => (-1.0, -1.0),
+            };
+            (gx * (fx - i as f64) + gz * (fz - j as f64), gx, gz)
+        };
+        let (g00, gx00, gz00) = corner(0, 0);
+        let (g10, gx10, gz10) = corner(1, 0);
+        let (g01, gx01, gz01) = corner(0, 1);
+        let (g11, gx11, gz11) = corner(1, 1);
+
+        let (u, du) = quintic(fx);
+        let (v, dv) = quintic(fz);
+
+        let a = g00 + v * (g01 - g00);
+        let b = g10 + v * (g11 - g10);
+        let value = (a + u * (b - a)) * SCALE_FACTOR;
+        if value.abs() > 1.0 {
+            // clamped like `perlin_2d`, flat past the clamp
+            return (value.clamp(-1.0, 1.0), [0.0, 0.0]);
+        }
+
+        let da_dx = gx00 + v * (gx01 - gx00);
+        let db_dx = gx10 + v * (gx11 - gx10);
+        let da_dz = gz00 + v * (gz01 - gz00) + dv * (g01 - g00);
+        let db_dz = gz10 + v * (gz11 - gz10) + dv * (g11 - g10);
+        let dx = da_dx + u * (db_dx - da_dx) + du * (b - a);
+        let dz = da_dz + u * (db_dz - da_dz);
+        (value, [dx * SCALE_FACTOR, dz * SCALE_FACTOR])
+    }
+}
+
+impl NoiseFn<f64, 2> for Perlin {
+    fn get(&self, point: [f64; 2]) -> f64 {
+        noise::core::perlin::perlin_2d(point.into(), &self.table)
+    }
+}
+
+/// a 2D noise basis the fractal sources stack into octaves, sampled in noise
+/// space (one feature per unit)
+pub trait Noise2: Send + Sync + 'static {
+    fn sample(&self, x: f64, z: f64) -> f64;
+
+    /// value and d/dx, d/dz. central differences unless the basis knows better
+    fn sample_with_gradient(&self, x: f64, z: f64) -> (f64, [f64; 2]) {
+        const EPS: f64 = 1e-4;
+        let dx = self.sample(x + EPS, z) - self.sample(x - EPS, z);
+        let dz = self.sample(x, z + EPS) - self.sample(x, z - EPS);
+        (self.sample(x, z), [dx / (2.0 * EPS), dz / (2.0 * EPS)])
+    }
+}
+
+impl Noise2 for Perlin {
+    fn sample(&self, x: f64, z: f64) -> f64 {
+        self.get([x, z])
+    }
+
+    fn sample_with_gradient(&self, x: f64, z: f64) -> (f64, [f64; 2]) {
+        self.value_and_gradient(x, z)
+    }
+}
+
+macro_rules! noise_crate_basis {
+    ($($t:ty),*) => {$(
+        impl Noise2 for $t {
+            fn sample(&self, x: f64, z: f64) -> f64 {
+                self.get([x, z])
+            }
+        }
+    )*};
+}
+
+noise_crate_basis!(
+    noise::OpenSimplex,
+    noise::Simplex,
+    noise::SuperSimplex,
+    noise::Value
+);
+
+impl Noise2 for Box<dyn Noise2> {
+    fn sample(&self, x: f64, z: f64) -> f64 {
+        (**self).sample(x, z)
+    }
+
+    fn sample_with_gradient(&self, x: f64, z: f64) -> (f64, [f64; 2]) {
+        (**self).sample_with_gradient(x, z)
+    }
+}
+
+/// which distance a `Worley` sample returns
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum WorleyMode {
+    /// distance to the nearest site: rounded cells, lowest at their centres
+    F1,
+    /// distance to the second nearest site
+    F2,
+    /// zero along cell borders and rising inward, polygonal plates split by cracks
+    F2MinusF1,
+}
+
+/// cellular noise, one jittered site per unit cell. distances are in cell
+/// units, so samples run from 0 to a little over 1
+#[derive(Clone, Copy, Debug)]
+pub struct Worley {
+    pub seed: u32,
+    pub mode: WorleyMode,
+    /// how far sites stray from their cell centres, 0 gives a square grid, 1 the full cell
+    pub jitter: f64,
+}
+
+impl Worley {
+    pub fn new(seed: u32, mode: WorleyMode) -> Self {
+        Self {
+            seed,
+            mode,
+            jitter: 1.0,
+        }
+    }
+}
+
+impl Noise2 for Worley {
+    fn sample(&self, x: f64, z: f64) -> f64 {
+        let (cx, cz) = (x.floor() as i32, z.floor() as i32);
+        let (mut f1, mut f2) = (f64::MAX, f64::MAX);
+        for dz in -1..=1 {
+            for dx in -1..=1 {
+                let (ix, iz) = (cx + dx, cz + dz);
+                let mut rng = HashRng(hash_cell(self.seed, ix, iz));
+                let sx = ix as f64 + 0.5 + self.jitter * (rng.next_f32() as f64 - 0.5);
+                let sz = iz as f64 + 0.5 + self.jitter * (rng.next_f32() as f64 - 0.5);
+                let d = ((x - sx).powi(2) + (z - sz).powi(2)).sqrt();
+                if d < f1 {
+                    (f1, f2) = (d, f1);
+                } else if d < f2 {
+                    f2 = d;
+                }
+            }
+        }
+        match self.mode {
+            WorleyMode::F1 => f1,
+            WorleyMode::F2 => f2,
+            WorleyMode::F2MinusF1 => f2 - f1,
+        }
+    }
+}
+
+/// serializable choice of `Noise2`, for recipes
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum NoiseBasis {
+    Perlin,
+    OpenSimplex,
+    Simplex,
+    SuperSimplex,
+    Value,
+    Worley {
+        mode: WorleyMode,
+        #[serde(default = "full_jitter")]
+        jitter: f64,
+    },
+}
+
+fn full_jitter() -> f64 {
+    1.0
+}
+
+impl NoiseBasis {
+    pub fn build(self, seed: u32) -> Box<dyn Noise2> {
+        match self {
+            NoiseBasis::Perlin => Box::new(Perlin::new(seed)),
+            NoiseBasis::OpenSimplex => Box::new(noise::OpenSimplex::new(seed)),
+            NoiseBasis::Simplex => Box::new(noise::Simplex::new(seed)),
+            NoiseBasis::SuperSimplex => Box::new(noise::SuperSimplex::new(seed)),
+            NoiseBasis::Value => Box::new(noise::Value::new(seed)),
+            NoiseBasis::Worley { mode, jitter } => Box::new(Worley { seed, mode, jitter }),
+        }
+    }
+}
+
+/// octave stacking shared by the fractal sources. `freq` is in cycles per
+/// meter, each octave multiplies it by `lacunarity` and its weight by `gain`,
+/// and the normalized sum is scaled to `amplitude`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Fractal {
     pub freq: f32,
     pub octaves: u32,
     pub lacunarity: f32,
@@ -10,53 +223,368 @@ pub struct PerlinFbm {
     pub amplitude: f32,
 }
 
-impl HeightSource for PerlinFbm {
-    fn height_at(&self, x: f32, z: f32) -> f32 {
+impl Fractal {
+    /// a single octave
+    pub fn single(freq: f32, amplitude: f32) -> Self {
+        Self {
+            freq,
+            octaves: 1,
+            lacunarity: 2.0,
+            gain: 0.5,
+            amplitude,
+        }
+    }
+}
+
+/// quintic fade curve and its derivative
+fn quintic(t: f64) -> (f64, f64) {
+    (
+        t * t * t * (t * (t * 6.0 - 15.0) + 10.0),
+        30.0 * t * t * (t - 1.0) * (t - 1.0),
+    )
+}
+
+/// fractal brownian motion: plain octave sum of `noise`
+pub struct Fbm<N: Noise2> {
+    pub noise: N,
+    pub fractal: Fractal,
+}
+
+pub type PerlinFbm = Fbm<Perlin>;
+
+impl<N: Noise2> HeightSource for Fbm<N> {
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        let fr = &self.fractal;
         let mut a = 1.0;
         let mut sum = 0.0;
         let mut amp = 0.0;
-        let mut fx = x * self.freq;
-        let mut fz = z * self.freq;
+        let mut fx = p.x * fr.freq as f64;
+        let mut fz = p.y * fr.freq as f64;
+
+        for _ in 0..fr.octaves {
+            sum += a * self.noise.sample(fx, fz) as f32;
+            amp += a;
+            fx *= fr.lacunarity as f64;
+            fz *= fr.lacunarity as f64;
+            a *= fr.gain;
+        }
+
+        (sum / amp) * fr.amplitude
+    }
+
+    fn height_and_gradient_world(&self, p: DVec2) -> (f32, Vec2) {
+        let fr = &self.fractal;
+        let mut a = 1.0;
+        let mut sum = 0.0;
+        let mut grad = Vec2::ZERO;
+        let mut amp = 0.0;
+        let mut f = fr.freq;
+        let mut fx = p.x * fr.freq as f64;
+        let mut fz = p.y * fr.freq as f64;
+
+        for _ in 0..fr.octaves {
+            let (n, [dx, dz]) = self.noise.sample_with_gradient(fx, fz);
+            sum += a * n as f32;
+            grad += a * f * Vec2::new(dx as f32, dz as f32);
+            amp += a;
+            fx *= fr.lacunarity as f64;
+            fz *= fr.lacunarity as f64;
+            f *= fr.lacunarity;
+            a *= fr.gain;
+        }
+
+        let k = fr.amplitude / amp;
+        (sum * k, grad * k)
+    }
+
+    fn sample_points(&self, points: &[DVec2], out: &mut [f32]) {
+        let fr = &self.fractal;
+        let out = &mut out[..points.len()];
+        let (mut fx, mut fz) = noise_coords(points, fr.freq);
+        out.fill(0.0);
+
+        // octave-major so each pass streams through the points once
+        let mut a = 1.0;
+        let mut amp = 0.0;
+        for _ in 0..fr.octaves {
+            for ((h, &x), &z) in out.iter_mut().zip(&fx).zip(&fz) {
+                *h += a * self.noise.sample(x, z) as f32;
+            }
+            amp += a;
+            fx.iter_mut().for_each(|x| *x *= fr.lacunarity as f64);
+            fz.iter_mut().for_each(|z| *z *= fr.lacunarity as f64);
+            a *= fr.gain;
+        }
+        out.iter_mut().for_each(|h| *h = (*h / amp) * fr.amplitude);
+    }
+
+    fn sample_points_with_gradient(&self, points: &[DVec2], out: &mut [f32], grad: &mut [Vec2]) {
+        let fr = &self.fractal;
+        let (out, grad) = (&mut out[..points.len()], &mut grad[..points.len()]);
+        let (mut fx, mut fz) = noise_coords(points, fr.freq);
+        out.fill(0.0);
+        grad.fill(Vec2::ZERO);
 
-        for _ in 0..self.octaves {
-            sum += a * self.perlin.get([fx as f64, fz as f64]) as f32;
+        let mut a = 1.0;
+        let mut amp = 0.0;
+        let mut f = fr.freq;
+        for _ in 0..fr.octaves {
+            for (k, (h, g)) in out.iter_mut().zip(grad.iter_mut()).enumerate() {
+                let (n, [dx, dz]) = self.noise.sample_with_gradient(fx[k], fz[k]);
+                *h += a * n as f32;
+                *g += a * f * Vec2::new(dx as f32, dz as f32);
+            }
             amp += a;
-            fx *= self.lacunarity;
-            fz *= self.lacunarity;
-            a *= self.gain;
+            fx.iter_mut().for_each(|x| *x *= fr.lacunarity as f64);
+            fz.iter_mut().for_each(|z| *z *= fr.lacunarity as f64);
+            f *= fr.lacunarity;
+            a *= fr.gain;
         }
 
-        (sum / amp) * self.amplitude
+        let k = fr.amplitude / amp;
+        out.iter_mut().for_each(|h| *h *= k);
+        grad.iter_mut().for_each(|g| *g *= k);
     }
 }
 
-pub struct PerlinRidged {
-    pub perlin: Perlin,
-    pub freq: f32,
-    pub octaves: u32,
-    pub lacunarity: f32,
-    pub gain: f32,
-    pub amplitude: f32,
+/// sharp crests where `noise` crosses zero, 0..`amplitude`. octaves squeeze
+/// along z by an extra `z_anisotropy` each step
+pub struct Ridged<N: Noise2> {
+    pub noise: N,
+    pub fractal: Fractal,
     pub z_anisotropy: f32,
 }
 
-impl HeightSource for PerlinRidged {
-    fn height_at(&self, x: f32, z: f32) -> f32 {
+pub type PerlinRidged = Ridged<Perlin>;
+
+impl<N: Noise2> HeightSource for Ridged<N> {
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        let fr = &self.fractal;
         let mut a = 1.0;
         let mut sum = 0.0;
         let mut amp = 0.0;
-        let mut fx = x * self.freq;
-        let mut fz = z * self.freq;
+        let mut fx = p.x * fr.freq as f64;
+        let mut fz = p.y * fr.freq as f64;
 
-        for _ in 0..self.octaves {
-            let v = 1.0 - (self.perlin.get([fx as f64, fz as f64]) as f32).abs();
+        for _ in 0..fr.octaves {
+            let v = 1.0 - (self.noise.sample(fx, fz) as f32).abs();
             sum += a * (v * v);
             amp += a;
-            fx *= self.lacunarity;
-            fz *= self.lacunarity * self.z_anisotropy;
-            a *= self.gain;
+            fx *= fr.lacunarity as f64;
+            fz *= (fr.lacunarity * self.z_anisotropy) as f64;
+            a *= fr.gain;
+        }
+
+        (sum / amp).clamp(0.0, 1.0) * fr.amplitude
+    }
+
+    fn height_and_gradient_world(&self, p: DVec2) -> (f32, Vec2) {
+        let fr = &self.fractal;
+        let mut a = 1.0;
+        let mut sum = 0.0;
+        let mut grad = Vec2::ZERO;
+        let mut amp = 0.0;
+        let mut f = Vec2::splat(fr.freq);
+        let mut fx = p.x * fr.freq as f64;
+        let mut fz = p.y * fr.freq as f64;
+
+        for _ in 0..fr.octaves {
+            let (n, [dx, dz]) = self.noise.sample_with_gradient(fx, fz);
+            let n = n as f32;
+            let v = 1.0 - n.abs();
+            sum += a * (v * v);
+            // d(1 - |n|)² = -2 (1 - |n|) sign(n) dn, taking the crest itself as flat
+            let sign = if n == 0.0 { 0.0 } else { n.signum() };
+            grad -= a * 2.0 * v * sign * f * Vec2::new(dx as f32, dz as f32);
+            amp += a;
+            fx *= fr.lacunarity as f64;
+            fz *= (fr.lacunarity * self.z_anisotropy) as f64;
+            f *= Vec2::new(fr.lacunarity, fr.lacunarity * self.z_anisotropy);
+            a *= fr.gain;
+        }
+
+        let t = sum / amp;
+        if !(0.0..=1.0).contains(&t) {
+            return (t.clamp(0.0, 1.0) * fr.amplitude, Vec2::ZERO);
+        }
+        (t * fr.amplitude, grad * (fr.amplitude / amp))
+    }
+
+    fn sample_points(&self, points: &[DVec2], out: &mut [f32]) {
+        let fr = &self.fractal;
+        let out = &mut out[..points.len()];
+        let (mut fx, mut fz) = noise_coords(points, fr.freq);
+        out.fill(0.0);
+
+        let mut a = 1.0;
+        let mut amp = 0.0;
+        for _ in 0..fr.octaves {
+            for ((h, &x), &z) in out.iter_mut().zip(&fx).zip(&fz) {
+                let v = 1.0 - (self.noise.sample(x, z) as f32).abs();
+                *h += a * (v * v);
+            }
+            amp += a;
+            fx.iter_mut().for_each(|x| *x *= fr.lacunarity as f64);
+            fz.iter_mut()
+                .for_each(|z| *z *= (fr.lacunarity * self.z_anisotropy) as f64);
+            a *= fr.gain;
+        }
+        out.iter_mut()
+            .for_each(|h| *h = (*h / amp).clamp(0.0, 1.0) * fr.amplitude);
+    }
+
+    fn sample_points_with_gradient(&self, points: &[DVec2], out: &mut [f32], grad: &mut [Vec2]) {
+        let fr = &self.fractal;
+        let (out, grad) = (&mut out[..points.len()], &mut grad[..points.len()]);
+        let (mut fx, mut fz) = noise_coords(points, fr.freq);
+        out.fill(0.0);
+        grad.fill(Vec2::ZERO);
+
+        let mut a = 1.0;
+        let mut amp = 0.0;
+        let mut f = Vec2::splat(fr.freq);
+        for _ in 0..fr.octaves {
+            for (k, (h, g)) in out.iter_mut().zip(grad.iter_mut()).enumerate() {
+                let (n, [dx, dz]) = self.noise.sample_with_gradient(fx[k], fz[k]);
+                let n = n as f32;
+                let v = 1.0 - n.abs();
+                let sign = if n == 0.0 { 0.0 } else { n.signum() };
+                *h += a * (v * v);
+                *g -= a * 2.0 * v * sign * f * Vec2::new(dx as f32, dz as f32);
+            }
+            amp += a;
+            fx.iter_mut().for_each(|x| *x *= fr.lacunarity as f64);
+            fz.iter_mut()
+                .for_each(|z| *z *= (fr.lacunarity * self.z_anisotropy) as f64);
+            f *= Vec2::new(fr.lacunarity, fr.lacunarity * self.z_anisotropy);
+            a *= fr.gain;
+        }
+
+        for (h, g) in out.iter_mut().zip(grad.iter_mut()) {
+            let t = *h / amp;
+            *h = t.clamp(0.0, 1.0) * fr.amplitude;
+            *g = if (0.0..=1.0).contains(&t) {
+                *g * (fr.amplitude / amp)
+            } else {
+                Vec2::ZERO
+            };
+        }
+    }
+}
+
+/// folded noise, `2|n| - 1` per octave: rounded puffy lumps with creased valleys
+pub struct Billow<N: Noise2> {
+    pub noise: N,
+    pub fractal: Fractal,
+}
+
+impl<N: Noise2> HeightSource for Billow<N> {
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        let fr = &self.fractal;
+        let mut a = 1.0;
+        let mut sum = 0.0;
+        let mut amp = 0.0;
+        let mut fx = p.x * fr.freq as f64;
+        let mut fz = p.y * fr.freq as f64;
+
+        for _ in 0..fr.octaves {
+            let n = self.noise.sample(fx, fz) as f32;
+            sum += a * (2.0 * n.abs() - 1.0);
+            amp += a;
+            fx *= fr.lacunarity as f64;
+            fz *= fr.lacunarity as f64;
+            a *= fr.gain;
         }
 
-        (sum / amp).clamp(0.0, 1.0) * self.amplitude
+        (sum / amp) * fr.amplitude
+    }
+}
+
+/// Musgrave's hybrid multifractal: each octave is weighted by the ones below
+/// it, so lowlands stay smooth and detail piles up on the highs. `offset`
+/// lifts the basis before weighting, around 0.7 for noise in -1..1.
+/// roughly 0..`amplitude`
+pub struct HybridMulti<N: Noise2> {
+    pub noise: N,
+    pub fractal: Fractal,
+    pub offset: f32,
+}
+
+impl<N: Noise2> HeightSource for HybridMulti<N> {
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        let fr = &self.fractal;
+        let mut a = 1.0;
+        let mut sum = 0.0;
+        let mut amp = 0.0;
+        let mut weight = 1.0_f32;
+        let mut fx = p.x * fr.freq as f64;
+        let mut fz = p.y * fr.freq as f64;
+
+        for _ in 0..fr.octaves {
+            let signal = (self.noise.sample(fx, fz) as f32 + self.offset) * a;
+            sum += weight * signal;
+            amp += a * (1.0 + self.offset);
+            weight = (weight * signal).min(1.0);
+            fx *= fr.lacunarity as f64;
+            fz *= fr.lacunarity as f64;
+            a *= fr.gain;
+        }
+
+        (sum / amp) * fr.amplitude
+    }
+}
+
+/// per-point noise-space coordinates, scaled by `freq` the same way
+/// `height_at` does it
+pub(super) fn noise_coords(points: &[DVec2], freq: f32) -> (Vec<f64>, Vec<f64>) {
+    points
+        .iter()
+        .map(|&p| p * freq as f64)
+        .map(|p| (p.x, p.y))
+        .unzip()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn worley_distances_are_ordered() {
+        let at = |mode| Worley::new(7, mode);
+        for k in 0..500 {
+            let (x, z) = (k as f64 * 0.173 - 40.0, k as f64 * 0.291 - 70.0);
+            let f1 = at(WorleyMode::F1).sample(x, z);
+            let f2 = at(WorleyMode::F2).sample(x, z);
+            let crack = at(WorleyMode::F2MinusF1).sample(x, z);
+            assert!((0.0..=f2).contains(&f1));
+            assert!((crack - (f2 - f1)).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn every_basis_stays_in_range() {
+        let bases = [
+            NoiseBasis::Perlin,
+            NoiseBasis::OpenSimplex,
+            NoiseBasis::Simplex,
+            NoiseBasis::SuperSimplex,
+            NoiseBasis::Value,
+        ];
+        for basis in bases {
+            let fbm = Fbm {
+                noise: basis.build(3),
+                fractal: Fractal {
+                    freq: 0.01,
+                    octaves: 4,
+                    lacunarity: 2.0,
+                    gain: 0.5,
+                    amplitude: 10.0,
+                },
+            };
+            for k in 0..200 {
+                let h = fbm.height_at(k as f32 * 13.7, k as f32 * -7.3);
+                assert!(h.is_finite() && h.abs() <= 10.0 + 1e-3, "{basis:?}: {h}");
+            }
+        }
     }
 }
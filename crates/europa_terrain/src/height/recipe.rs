@@ -0,0 +1,718 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::bands::Bands;
+use super::chaos::ChaosTerrain;
+use super::comb::CurveInterp;
+use super::craters::Craters;
+use super::lenticulae::{Lenticulae, RadialProfile};
+use super::noise::{
+    Billow, Fbm, Fractal, HybridMulti, NoiseBasis, Perlin, PerlinFbm, PerlinRidged, Ridged,
+};
+use super::ridges::DoubleRidges;
+use super::{HeightFn, arc, comb, warp};
+use crate::asset::RecipeError;
+use crate::generator::europa_height;
+use crate::params::TerrainParams;
+
+/// serializable mirror of the `height` combinators.
+///
+/// noise nodes carry a `salt` rather than a seed: the graph is built against a
+/// base seed and each node uses `seed ^ salt`, so one recipe yields a family of
+/// terrains and the seed can live in `TerrainParams`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum HeightRecipe {
+    /// `europa_height` from the current `TerrainParams`, so `amp`, `freq` and
+    /// `line_dir` keep shaping a recipe built on top of it
+    Generator,
+    PerlinFbm {
+        #[serde(default)]
+        salt: u32,
+        freq: f32,
+        octaves: u32,
+        lacunarity: f32,
+        gain: f32,
+        amplitude: f32,
+    },
+    PerlinRidged {
+        #[serde(default)]
+        salt: u32,
+        freq: f32,
+        octaves: u32,
+        lacunarity: f32,
+        gain: f32,
+        amplitude: f32,
+        z_anisotropy: f32,
+    },
+    /// any `NoiseBasis` stacked by `Fractal`; `Worley` bases give cellular plates
+    Fbm {
+        basis: NoiseBasis,
+        #[serde(default)]
+        salt: u32,
+        fractal: Fractal,
+    },
+    Ridged {
+        basis: NoiseBasis,
+        #[serde(default)]
+        salt: u32,
+        fractal: Fractal,
+        #[serde(default = "one")]
+        z_anisotropy: f32,
+    },
+    Billow {
+        basis: NoiseBasis,
+        #[serde(default)]
+        salt: u32,
+        fractal: Fractal,
+    },
+    HybridMulti {
+        basis: NoiseBasis,
+        #[serde(default)]
+        salt: u32,
+        fractal: Fractal,
+        offset: f32,
+    },
+    Craters {
+        #[serde(default)]
+        salt: u32,
+        density: f32,
+        d_min: f32,
+        d_max: f32,
+        slope: f32,
+        depth_ratio: f32,
+        rim_ratio: f32,
+        complex_diameter: f32,
+        ejecta_extent: f32,
+    },
+    DoubleRidges {
+        #[serde(default)]
+        salt: u32,
+        density: f32,
+        length_min: f32,
+        length_max: f32,
+        width_min: f32,
+        width_max: f32,
+        height_ratio: f32,
+        trough: f32,
+        curvature: f32,
+        wiggle: f32,
+        segments: u32,
+    },
+    ChaosTerrain {
+        source: Box<HeightRecipe>,
+        #[serde(default)]
+        salt: u32,
+        raft_size: f32,
+        raft_fraction: f32,
+        gap: f32,
+        edge: f32,
+        max_shift: f32,
+        max_rotation: f32,
+        max_tilt: f32,
+        max_lift: f32,
+        matrix_depth: f32,
+        hummock_amp: f32,
+        hummock_freq: f32,
+    },
+    Bands {
+        source: Box<HeightRecipe>,
+        #[serde(default)]
+        salt: u32,
+        density: f32,
+        length_min: f32,
+        length_max: f32,
+        width_min: f32,
+        width_max: f32,
+        shear: f32,
+        floor_height: f32,
+        edge: f32,
+        groove_amp: f32,
+        groove_spacing: f32,
+    },
+    Lenticulae {
+        #[serde(default)]
+        salt: u32,
+        density: f32,
+        diameter_min: f32,
+        diameter_max: f32,
+        dome_weight: f32,
+        pit_weight: f32,
+        chaos_weight: f32,
+        dome_height: f32,
+        pit_depth: f32,
+        chaos_depth: f32,
+        dome_profile: RadialProfile,
+        pit_profile: RadialProfile,
+        chaos_roughness: f32,
+        chaos_freq: f32,
+        max_elongation: f32,
+    },
+    Add2 {
+        a: Box<HeightRecipe>,
+        b: Box<HeightRecipe>,
+    },
+    Scale {
+        s: Box<HeightRecipe>,
+        scale: f32,
+    },
+    Bias {
+        s: Box<HeightRecipe>,
+        bias: f32,
+    },
+    Warp2D {
+        source: Box<HeightRecipe>,
+        #[serde(default)]
+        salt: u32,
+        warp_amp: f32,
+        warp_freq: f32,
+        octaves: u32,
+        lacunarity: f32,
+        gain: f32,
+    },
+    Mul {
+        a: Box<HeightRecipe>,
+        b: Box<HeightRecipe>,
+    },
+    Min {
+        a: Box<HeightRecipe>,
+        b: Box<HeightRecipe>,
+    },
+    Max {
+        a: Box<HeightRecipe>,
+        b: Box<HeightRecipe>,
+    },
+    SmoothMin {
+        a: Box<HeightRecipe>,
+        b: Box<HeightRecipe>,
+        k: f32,
+    },
+    SmoothMax {
+        a: Box<HeightRecipe>,
+        b: Box<HeightRecipe>,
+        k: f32,
+    },
+    Clamp {
+        s: Box<HeightRecipe>,
+        min: f32,
+        max: f32,
+    },
+    Abs {
+        s: Box<HeightRecipe>,
+    },
+    Terrace {
+        s: Box<HeightRecipe>,
+        step: f32,
+        sharpness: f32,
+    },
+    Curve {
+        s: Box<HeightRecipe>,
+        /// `(input, output)` pairs, sorted when built
+        points: Vec<[f32; 2]>,
+        interp: CurveInterp,
+    },
+    Lerp {
+        a: Box<HeightRecipe>,
+        b: Box<HeightRecipe>,
+        mask: Box<HeightRecipe>,
+    },
+    Select {
+        a: Box<HeightRecipe>,
+        b: Box<HeightRecipe>,
+        mask: Box<HeightRecipe>,
+        threshold: f32,
+        #[serde(default)]
+        falloff: f32,
+    },
+    Sum {
+        items: Vec<HeightRecipe>,
+    },
+    Oriented {
+        source: Box<HeightRecipe>,
+        /// normalized when built
+        dir: [f32; 2],
+        main_scale: f32,
+        ortho_scale: f32,
+    },
+    /// `s` times `TerrainParams::amp`, so it can be authored in units of it
+    Amp {
+        s: Box<HeightRecipe>,
+    },
+    /// `source` measured in base wavelengths, `1 / TerrainParams::freq`: its
+    /// frequencies are multiples of `freq` and its distances, like a
+    /// `warp_amp`, fractions of the wavelength
+    Freq {
+        source: Box<HeightRecipe>,
+    },
+    /// `Oriented` along `TerrainParams::line_dir`
+    AlongLineDir {
+        source: Box<HeightRecipe>,
+        main_scale: f32,
+        ortho_scale: f32,
+    },
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+/// more octaves than this add nothing an f32 height can hold
+const MAX_OCTAVES: u32 = 24;
+
+fn positive(v: f32) -> bool {
+    v.is_finite() && v > 0.0
+}
+
+/// `RecipeError::Invalid` naming `node` unless `ok`
+fn check(ok: bool, node: &str, needs: &str) -> Result<(), RecipeError> {
+    if ok {
+        Ok(())
+    } else {
+        Err(RecipeError::Invalid(format!("{node} needs {needs}")))
+    }
+}
+
+/// octave stacks normalize by their summed gains, so none may be zero or
+/// negative, and frequencies must be usable
+fn check_fractal(node: &str, f: &Fractal) -> Result<(), RecipeError> {
+    check(
+        (1..=MAX_OCTAVES).contains(&f.octaves),
+        node,
+        &format!("1 to {MAX_OCTAVES} octaves, got {}", f.octaves),
+    )?;
+    check(
+        positive(f.freq) && positive(f.lacunarity) && positive(f.gain),
+        node,
+        &format!(
+            "positive freq, lacunarity and gain, got {}, {}, {}",
+            f.freq, f.lacunarity, f.gain
+        ),
+    )?;
+    check(
+        f.amplitude.is_finite(),
+        node,
+        &format!("a finite amplitude, got {}", f.amplitude),
+    )
+}
+
+/// `0 < min <= max`, for the size ranges features are drawn from
+fn check_range(node: &str, what: &str, min: f32, max: f32) -> Result<(), RecipeError> {
+    check(
+        positive(min) && positive(max) && min <= max,
+        node,
+        &format!("0 < {what}_min <= {what}_max, got {min}, {max}"),
+    )
+}
+
+impl HeightRecipe {
+    /// the graph against `p`, noise seeded from `p.seed`. fails on parameters
+    /// a node can't sample with
+    pub fn build(&self, p: &TerrainParams) -> Result<HeightFn, RecipeError> {
+        let seed = p.seed;
+        Ok(match self {
+            HeightRecipe::Generator => europa_height(p),
+            HeightRecipe::PerlinFbm {
+                salt,
+                freq,
+                octaves,
+                lacunarity,
+                gain,
+                amplitude,
+            } => {
+                let fractal = Fractal {
+                    freq: *freq,
+                    octaves: *octaves,
+                    lacunarity: *lacunarity,
+                    gain: *gain,
+                    amplitude: *amplitude,
+                };
+                check_fractal("PerlinFbm", &fractal)?;
+                arc(PerlinFbm {
+                    noise: Perlin::new(seed ^ salt),
+                    fractal,
+                })
+            }
+            HeightRecipe::PerlinRidged {
+                salt,
+                freq,
+                octaves,
+                lacunarity,
+                gain,
+                amplitude,
+                z_anisotropy,
+            } => {
+                let fractal = Fractal {
+                    freq: *freq,
+                    octaves: *octaves,
+                    lacunarity: *lacunarity,
+                    gain: *gain,
+                    amplitude: *amplitude,
+                };
+                check_fractal("PerlinRidged", &fractal)?;
+                arc(PerlinRidged {
+                    noise: Perlin::new(seed ^ salt),
+                    fractal,
+                    z_anisotropy: *z_anisotropy,
+                })
+            }
+            HeightRecipe::Fbm {
+                basis,
+                salt,
+                fractal,
+            } => {
+                check_fractal("Fbm", fractal)?;
+                arc(Fbm {
+                    noise: basis.build(seed ^ salt),
+                    fractal: *fractal,
+                })
+            }
+            HeightRecipe::Ridged {
+                basis,
+                salt,
+                fractal,
+                z_anisotropy,
+            } => {
+                check_fractal("Ridged", fractal)?;
+                arc(Ridged {
+                    noise: basis.build(seed ^ salt),
+                    fractal: *fractal,
+                    z_anisotropy: *z_anisotropy,
+                })
+            }
+            HeightRecipe::Billow {
+                basis,
+                salt,
+                fractal,
+            } => {
+                check_fractal("Billow", fractal)?;
+                arc(Billow {
+                    noise: basis.build(seed ^ salt),
+                    fractal: *fractal,
+                })
+            }
+            HeightRecipe::HybridMulti {
+                basis,
+                salt,
+                fractal,
+                offset,
+            } => {
+                check_fractal("HybridMulti", fractal)?;
+                arc(HybridMulti {
+                    noise: basis.build(seed ^ salt),
+                    fractal: *fractal,
+                    offset: *offset,
+                })
+            }
+            HeightRecipe::Craters {
+                salt,
+                density,
+                d_min,
+                d_max,
+                slope,
+                depth_ratio,
+                rim_ratio,
+                complex_diameter,
+                ejecta_extent,
+            } => {
+                // the octave bins double from d_min and must reach d_max
+                if !(*d_min > 0.0 && d_min < d_max && d_max.is_finite()) {
+                    return Err(RecipeError::Invalid(format!(
+                        "Craters needs 0 < d_min < d_max, got d_min {d_min}, d_max {d_max}"
+                    )));
+                }
+                arc(Craters {
+                    seed: seed ^ salt,
+                    density: *density,
+                    d_min: *d_min,
+                    d_max: *d_max,
+                    slope: *slope,
+                    depth_ratio: *depth_ratio,
+                    rim_ratio: *rim_ratio,
+                    complex_diameter: *complex_diameter,
+                    ejecta_extent: *ejecta_extent,
+                })
+            }
+            HeightRecipe::DoubleRidges {
+                salt,
+                density,
+                length_min,
+                length_max,
+                width_min,
+                width_max,
+                height_ratio,
+                trough,
+                curvature,
+                wiggle,
+                segments,
+            } => {
+                check_range("DoubleRidges", "length", *length_min, *length_max)?;
+                check_range("DoubleRidges", "width", *width_min, *width_max)?;
+                arc(DoubleRidges {
+                    seed: seed ^ salt,
+                    density: *density,
+                    length_min: *length_min,
+                    length_max: *length_max,
+                    width_min: *width_min,
+                    width_max: *width_max,
+                    height_ratio: *height_ratio,
+                    trough: *trough,
+                    curvature: *curvature,
+                    wiggle: *wiggle,
+                    segments: *segments,
+                })
+            }
+            HeightRecipe::ChaosTerrain {
+                source,
+                salt,
+                raft_size,
+                raft_fraction,
+                gap,
+                edge,
+                max_shift,
+                max_rotation,
+                max_tilt,
+                max_lift,
+                matrix_depth,
+                hummock_amp,
+                hummock_freq,
+            } => {
+                check(
+                    positive(*raft_size),
+                    "ChaosTerrain",
+                    &format!("a positive raft_size, got {raft_size}"),
+                )?;
+                arc(ChaosTerrain {
+                    source: source.build(p)?,
+                    seed: seed ^ salt,
+                    perlin: Perlin::new(seed ^ salt ^ 0x2c1b_3c6d),
+                    raft_size: *raft_size,
+                    raft_fraction: *raft_fraction,
+                    gap: *gap,
+                    edge: *edge,
+                    max_shift: *max_shift,
+                    max_rotation: *max_rotation,
+                    max_tilt: *max_tilt,
+                    max_lift: *max_lift,
+                    matrix_depth: *matrix_depth,
+                    hummock_amp: *hummock_amp,
+                    hummock_freq: *hummock_freq,
+                })
+            }
+            HeightRecipe::Bands {
+                source,
+                salt,
+                density,
+                length_min,
+                length_max,
+                width_min,
+                width_max,
+                shear,
+                floor_height,
+                edge,
+                groove_amp,
+                groove_spacing,
+            } => {
+                check_range("Bands", "length", *length_min, *length_max)?;
+                check_range("Bands", "width", *width_min, *width_max)?;
+                arc(Bands {
+                    source: source.build(p)?,
+                    seed: seed ^ salt,
+                    density: *density,
+                    length_min: *length_min,
+                    length_max: *length_max,
+                    width_min: *width_min,
+                    width_max: *width_max,
+                    shear: *shear,
+                    floor_height: *floor_height,
+                    edge: *edge,
+                    groove_amp: *groove_amp,
+                    groove_spacing: *groove_spacing,
+                })
+            }
+            HeightRecipe::Lenticulae {
+                salt,
+                density,
+                diameter_min,
+                diameter_max,
+                dome_weight,
+                pit_weight,
+                chaos_weight,
+                dome_height,
+                pit_depth,
+                chaos_depth,
+                dome_profile,
+                pit_profile,
+                chaos_roughness,
+                chaos_freq,
+                max_elongation,
+            } => {
+                check_range("Lenticulae", "diameter", *diameter_min, *diameter_max)?;
+                arc(Lenticulae {
+                    seed: seed ^ salt,
+                    perlin: Perlin::new(seed ^ salt ^ 0x7f4a_7c15),
+                    density: *density,
+                    diameter_min: *diameter_min,
+                    diameter_max: *diameter_max,
+                    dome_weight: *dome_weight,
+                    pit_weight: *pit_weight,
+                    chaos_weight: *chaos_weight,
+                    dome_height: *dome_height,
+                    pit_depth: *pit_depth,
+                    chaos_depth: *chaos_depth,
+                    dome_profile: *dome_profile,
+                    pit_profile: *pit_profile,
+                    chaos_roughness: *chaos_roughness,
+                    chaos_freq: *chaos_freq,
+                    max_elongation: *max_elongation,
+                })
+            }
+            HeightRecipe::Add2 { a, b } => arc(comb::Add2 {
+                a: a.build(p)?,
+                b: b.build(p)?,
+            }),
+            HeightRecipe::Scale { s, scale } => arc(comb::Scale {
+                s: s.build(p)?,
+                scale: *scale,
+            }),
+            HeightRecipe::Bias { s, bias } => arc(comb::Bias {
+                s: s.build(p)?,
+                bias: *bias,
+            }),
+            HeightRecipe::Warp2D {
+                source,
+                salt,
+                warp_amp,
+                warp_freq,
+                octaves,
+                lacunarity,
+                gain,
+            } => {
+                // the displacement is an octave stack too
+                check_fractal(
+                    "Warp2D",
+                    &Fractal {
+                        freq: *warp_freq,
+                        octaves: *octaves,
+                        lacunarity: *lacunarity,
+                        gain: *gain,
+                        amplitude: *warp_amp,
+                    },
+                )?;
+                arc(warp::Warp2D {
+                    source: source.build(p)?,
+                    perlin: Perlin::new(seed ^ salt),
+                    warp_amp: *warp_amp,
+                    warp_freq: *warp_freq,
+                    octaves: *octaves,
+                    lacunarity: *lacunarity,
+                    gain: *gain,
+                })
+            }
+            HeightRecipe::Mul { a, b } => arc(comb::Mul {
+                a: a.build(p)?,
+                b: b.build(p)?,
+            }),
+            HeightRecipe::Min { a, b } => arc(comb::Min {
+                a: a.build(p)?,
+                b: b.build(p)?,
+            }),
+            HeightRecipe::Max { a, b } => arc(comb::Max {
+                a: a.build(p)?,
+                b: b.build(p)?,
+            }),
+            HeightRecipe::SmoothMin { a, b, k } => arc(comb::SmoothMin {
+                a: a.build(p)?,
+                b: b.build(p)?,
+                k: *k,
+            }),
+            HeightRecipe::SmoothMax { a, b, k } => arc(comb::SmoothMax {
+                a: a.build(p)?,
+                b: b.build(p)?,
+                k: *k,
+            }),
+            HeightRecipe::Clamp { s, min, max } => {
+                if !(min.is_finite() && max.is_finite() && min <= max) {
+                    return Err(RecipeError::Invalid(format!(
+                        "Clamp needs finite min <= max, got min {min}, max {max}"
+                    )));
+                }
+                arc(comb::Clamp {
+                    s: s.build(p)?,
+                    min: *min,
+                    max: *max,
+                })
+            }
+            HeightRecipe::Abs { s } => arc(comb::Abs { s: s.build(p)? }),
+            HeightRecipe::Terrace { s, step, sharpness } => arc(comb::Terrace {
+                s: s.build(p)?,
+                step: *step,
+                sharpness: *sharpness,
+            }),
+            HeightRecipe::Curve { s, points, interp } => {
+                let mut points: Vec<Vec2> = points.iter().map(|&p| Vec2::from(p)).collect();
+                points.sort_by(|a, b| a.x.total_cmp(&b.x));
+                arc(comb::Curve {
+                    s: s.build(p)?,
+                    points,
+                    interp: *interp,
+                })
+            }
+            HeightRecipe::Lerp { a, b, mask } => arc(comb::Lerp {
+                a: a.build(p)?,
+                b: b.build(p)?,
+                mask: mask.build(p)?,
+            }),
+            HeightRecipe::Select {
+                a,
+                b,
+                mask,
+                threshold,
+                falloff,
+            } => arc(comb::Select {
+                a: a.build(p)?,
+                b: b.build(p)?,
+                mask: mask.build(p)?,
+                threshold: *threshold,
+                falloff: *falloff,
+            }),
+            HeightRecipe::Sum { items } => arc(comb::Sum {
+                items: items.iter().map(|r| r.build(p)).collect::<Result<_, _>>()?,
+            }),
+            HeightRecipe::Oriented {
+                source,
+                dir,
+                main_scale,
+                ortho_scale,
+            } => arc(warp::Oriented {
+                source: source.build(p)?,
+                dir: Vec2::from(*dir).normalize_or(Vec2::X),
+                main_scale: *main_scale,
+                ortho_scale: *ortho_scale,
+            }),
+            HeightRecipe::Amp { s } => arc(comb::Scale {
+                s: s.build(p)?,
+                scale: p.amp,
+            }),
+            HeightRecipe::Freq { source } => {
+                check(
+                    positive(p.freq),
+                    "Freq",
+                    &format!("a positive TerrainParams::freq, got {}", p.freq),
+                )?;
+                arc(warp::Zoom {
+                    source: source.build(p)?,
+                    scale: p.freq as f64,
+                })
+            }
+            HeightRecipe::AlongLineDir {
+                source,
+                main_scale,
+                ortho_scale,
+            } => arc(warp::Oriented {
+                source: source.build(p)?,
+                dir: p.line_dir.normalize_or(Vec2::X),
+                main_scale: *main_scale,
+                ortho_scale: *ortho_scale,
+            }),
+        })
+    }
+}
@@ -0,0 +1,298 @@
+use bevy::math::DVec2;
+use bevy::prelude::*;
+use europa_math::{HashRng, hash_cell, smoothstep};
+
+use super::HeightSource;
+
+/// Europa's double ridges: pairs of crests with a medial trough, running along
+/// gently curving paths of any orientation.
+///
+/// ridges are scattered into a lattice of cells at least as wide as the longest
+/// ridge, each gets a random age and younger ridges overwrite older ones inside
+/// their footprint, so crossings show the usual cross-cutting order
+pub struct DoubleRidges {
+    pub seed: u32,
+    /// ridges per km²
+    pub density: f32,
+    /// path length range in meters
+    pub length_min: f32,
+    pub length_max: f32,
+    /// full width across both flanks, meters
+    pub width_min: f32,
+    pub width_max: f32,
+    /// crest height / width
+    pub height_ratio: f32,
+    /// trough floor as a fraction of crest height
+    pub trough: f32,
+    /// largest steady turn rate, radians per meter (an arc of radius 1/curvature)
+    pub curvature: f32,
+    /// random heading change per segment, radians
+    pub wiggle: f32,
+    /// polyline segments per ridge
+    pub segments: u32,
+}
+
+impl DoubleRidges {
+    pub fn europa(seed: u32) -> Self {
+        Self {
+            seed,
+            density: 0.5,
+            length_min: 1500.0,
+            length_max: 6000.0,
+            width_min: 80.0,
+            width_max: 400.0,
+            height_ratio: 0.12,
+            trough: 0.35,
+            curvature: 2e-4,
+            wiggle: 0.05,
+            segments: 12,
+        }
+    }
+
+    /// cross-section at `a = |offset| / half_width`, 1 at the crests
+    fn profile(&self, a: f32) -> f32 {
+        let crest = 0.45;
+        if a < crest {
+            let s = (a / crest * std::f32::consts::FRAC_PI_2).sin();
+            self.trough + (1.0 - self.trough) * s * s
+        } else {
+            1.0 - smoothstep(crest, 1.0, a)
+        }
+    }
+
+    fn ridge(&self, seed: u32, cell: f32, ix: i32, iz: i32) -> Ridge {
+        let mut rng = HashRng(seed);
+        let jitter = DVec2::new(rng.next_f32().into(), rng.next_f32().into());
+        let start = (IVec2::new(ix, iz).as_dvec2() + jitter) * cell as f64;
+        Ridge {
+            seed: rng.next_u32(),
+            start,
+            age: rng.next_f32(),
+            length: rng.range(self.length_min, self.length_max),
+            width: rng.range(self.width_min, self.width_max),
+        }
+    }
+
+    /// the polyline segments of the ridge path, relative to its start, in
+    /// single precision
+    fn path(&self, r: &Ridge) -> impl Iterator<Item = (Vec2, Vec2)> {
+        let mut rng = HashRng(r.seed);
+        let mut heading = rng.range(0.0, std::f32::consts::TAU);
+        let turn = rng.range(-self.curvature, self.curvature);
+        let seg = r.length / self.segments.max(1) as f32;
+        let wiggle = self.wiggle;
+
+        let mut a = Vec2::ZERO;
+        (0..self.segments.max(1)).map(move |_| {
+            let b = a + Vec2::from_angle(heading) * seg;
+            heading += turn * seg + rng.range(-wiggle, wiggle);
+            let ab = (a, b);
+            a = b;
+            ab
+        })
+    }
+
+    /// distance from `p` to the ridge path and arc length of the closest point
+    fn nearest(&self, r: &Ridge, p: DVec2) -> (f32, f32) {
+        let p = (p - r.start).as_vec2();
+        let seg = r.length / self.segments.max(1) as f32;
+        let mut best = (f32::INFINITY, 0.0);
+        for (k, (a, b)) in self.path(r).enumerate() {
+            let ab = b - a;
+            let t = ((p - a).dot(ab) / ab.length_squared()).clamp(0.0, 1.0);
+            let d = p.distance(a + ab * t);
+            if d < best.0 {
+                best = (d, (k as f32 + t) * seg);
+            }
+        }
+        best
+    }
+
+    /// every ridge whose footprint holds `p`, oldest first
+    fn stack(&self, p: DVec2) -> RidgeStack {
+        let cell = self.length_max + self.width_max;
+        let lambda = self.density * 1e-6 * cell * cell;
+        let cx = (p.x / cell as f64).floor() as i32;
+        let cz = (p.y / cell as f64).floor() as i32;
+
+        let mut stack = RidgeStack::default();
+        for dz in -1..=1 {
+            for dx in -1..=1 {
+                let (ix, iz) = (cx + dx, cz + dz);
+                let mut rng = HashRng(hash_cell(self.seed, ix, iz));
+                for _ in 0..rng.poisson(lambda) {
+                    let r = self.ridge(rng.next_u32(), cell, ix, iz);
+                    let half = r.width * 0.5;
+                    if p.distance(r.start) > (r.length + half) as f64 {
+                        continue;
+                    }
+                    let (d, s) = self.nearest(&r, p);
+                    if d >= half {
+                        continue;
+                    }
+                    // ridges pinch out over a couple of widths at either end
+                    let taper = smoothstep(0.0, 2.0 * r.width, s)
+                        * smoothstep(0.0, 2.0 * r.width, r.length - s);
+                    let a = d / half;
+                    stack.push(Hit {
+                        age: r.age,
+                        height: self.profile(a) * self.height_ratio * r.width * taper,
+                        cover: (1.0 - smoothstep(0.8, 1.0, a)) * taper,
+                    });
+                }
+            }
+        }
+        stack
+    }
+}
+
+struct Ridge {
+    seed: u32,
+    start: DVec2,
+    age: f32,
+    length: f32,
+    width: f32,
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+struct Hit {
+    age: f32,
+    height: f32,
+    cover: f32,
+}
+
+/// ridges stacked over one point at most; past that the oldest go, the
+/// younger ones on top have all but buried them
+const MAX_STACK: usize = 8;
+
+/// the ridges over one point in age order, on the stack since every height
+/// sample builds one
+#[derive(Default)]
+struct RidgeStack {
+    hits: [Hit; MAX_STACK],
+    len: usize,
+}
+
+impl RidgeStack {
+    fn push(&mut self, hit: Hit) {
+        let at = self.hits[..self.len].partition_point(|h| h.age <= hit.age);
+        if self.len < MAX_STACK {
+            self.hits.copy_within(at..self.len, at + 1);
+            self.hits[at] = hit;
+            self.len += 1;
+        } else if at > 0 {
+            self.hits.copy_within(1..at, 0);
+            self.hits[at - 1] = hit;
+        }
+    }
+
+    fn hits(&self) -> &[Hit] {
+        &self.hits[..self.len]
+    }
+}
+
+impl HeightSource for DoubleRidges {
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        self.stack(p)
+            .hits()
+            .iter()
+            .fold(0.0, |h, hit| h.lerp(hit.height, hit.cover))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cross_section_has_trough_between_crests() {
+        let r = DoubleRidges::europa(1);
+        assert!((r.profile(0.0) - r.trough).abs() < 1e-6);
+        assert!((r.profile(0.45) - 1.0).abs() < 1e-6);
+        assert!(r.profile(1.0).abs() < 1e-6);
+        assert!(r.profile(0.2) < r.profile(0.4));
+    }
+
+    #[test]
+    fn stack_keeps_age_order_and_drops_the_oldest() {
+        let mut stack = RidgeStack::default();
+        for k in 0..MAX_STACK + 3 {
+            // ages out of order: 0, 5, 10, 4, 9, 3, ...
+            let age = ((k * 5) % 11) as f32;
+            stack.push(Hit {
+                age,
+                height: age,
+                cover: 1.0,
+            });
+        }
+        let ages: Vec<f32> = stack.hits().iter().map(|h| h.age).collect();
+        assert_eq!(ages.len(), MAX_STACK);
+        assert!(ages.is_sorted(), "{ages:?}");
+        assert_eq!(ages.last(), Some(&10.0));
+        assert_eq!(ages[0], 3.0);
+    }
+
+    #[test]
+    fn younger_ridges_cut_across_older_ones() {
+        let r = DoubleRidges {
+            density: 4.0,
+            ..DoubleRidges::europa(7)
+        };
+        let mut crossings = 0;
+        for iz in -25..25 {
+            for ix in -25..25 {
+                let p = DVec2::new(ix as f64, iz as f64) * 53.0;
+                let stack = r.stack(p);
+                let [.., older, younger] = stack.hits() else {
+                    continue;
+                };
+                if younger.cover < 1.0 || (younger.height - older.height).abs() < 1.0 {
+                    continue;
+                }
+                crossings += 1;
+                // the younger ridge decides the height, whatever lies under it
+                let h = r.height_at_world(p);
+                assert!((h - younger.height).abs() < 1e-3, "{h} at {p}");
+            }
+        }
+        assert!(crossings > 5, "only {crossings} crossings");
+    }
+
+    #[test]
+    fn ridges_pinch_out_smoothly_at_their_ends() {
+        let r = DoubleRidges {
+            density: 0.2,
+            ..DoubleRidges::europa(11)
+        };
+        let cell = r.length_max + r.width_max;
+        let lone = (0..64)
+            .flat_map(|ix| {
+                let mut rng = HashRng(hash_cell(r.seed, ix, 0));
+                let n = rng.poisson(r.density * 1e-6 * cell * cell);
+                (0..n)
+                    .map(|_| r.ridge(rng.next_u32(), cell, ix, 0))
+                    .collect::<Vec<_>>()
+            })
+            .find(|ridge| {
+                let (_, end) = r.path(ridge).last().unwrap();
+                r.stack(ridge.start + end.as_dvec2()).hits().len() == 1
+            })
+            .expect("no ridge with a clear end");
+
+        let (a, b) = r.path(&lone).last().unwrap();
+        let dir = (b - a).normalize();
+        let end = lone.start + b.as_dvec2();
+        assert!(r.height_at_world(end).abs() < 1e-3, "ridge ends in a step");
+
+        // walk in along the crest line: heights rise without a jump
+        let crest = r.height_ratio * lone.width;
+        let mut last = r.height_at_world(end + dir.as_dvec2() * 10.0);
+        for k in 1..=100 {
+            let p = end - (dir * k as f32 * 2.0).as_dvec2();
+            let h = r.height_at_world(p);
+            assert!((h - last).abs() < 0.05 * crest, "{last} -> {h} at {p}");
+            last = h;
+        }
+        assert!(last > 0.1 * crest, "ridge never rose, {last}");
+    }
+}
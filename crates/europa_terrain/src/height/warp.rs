@@ -1,7 +1,9 @@
+use bevy::math::DVec2;
 use bevy::prelude::*;
-use noise::{NoiseFn, Perlin};
+use noise::NoiseFn;
 
 use super::HeightSource;
+use super::noise::{Perlin, noise_coords};
 
 pub struct Warp2D<S: HeightSource> {
     pub source: S,
@@ -14,34 +16,141 @@ pub struct Warp2D<S: HeightSource> {
 }
 
 impl<S: HeightSource> HeightSource for Warp2D<S> {
-    fn height_at(&self, x: f32, z: f32) -> f32 {
+    fn height_at_world(&self, p: DVec2) -> f32 {
         // small fbm field for displacement
         let mut a = 1.0;
         let mut sumx = 0.0;
         let mut sumz = 0.0;
         let mut amp = 0.0;
-        
-        let mut fx = x * self.warp_freq;
-        let mut fz = z * self.warp_freq;
-        
+
+        let mut fx = p.x * self.warp_freq as f64;
+        let mut fz = p.y * self.warp_freq as f64;
+
         for _ in 0..self.octaves {
             // two independent samples for x/z displacement
-            let nx = self.perlin.get([fx as f64, fz as f64]) as f32;
-            let nz = self.perlin.get([fz as f64, fx as f64]) as f32;
-            
+            let nx = self.perlin.get([fx, fz]) as f32;
+            let nz = self.perlin.get([fz, fx]) as f32;
+
             sumx += a * nx;
             sumz += a * nz;
             amp += a;
-            
-            fx *= self.lacunarity;
-            fz *= self .lacunarity;
+
+            fx *= self.lacunarity as f64;
+            fz *= self.lacunarity as f64;
+            a *= self.gain;
+        }
+
+        let w = Vec2::new(sumx, sumz) / amp * self.warp_amp;
+        self.source.height_at_world(p + w.as_dvec2())
+    }
+
+    fn height_and_gradient_world(&self, p: DVec2) -> (f32, Vec2) {
+        let mut a = 1.0;
+        let mut amp = 0.0;
+        let mut f = self.warp_freq;
+        let mut fx = p.x * self.warp_freq as f64;
+        let mut fz = p.y * self.warp_freq as f64;
+        let mut w = Vec2::ZERO;
+        // jacobian of the displacement, rows are d(wx) and d(wz) over (x, z)
+        let mut dwx = Vec2::ZERO;
+        let mut dwz = Vec2::ZERO;
+
+        for _ in 0..self.octaves {
+            let (nx, gx) = self.perlin.value_and_gradient(fx, fz);
+            // the z sample reads swapped coordinates, so its partials swap too
+            let (nz, gz) = self.perlin.value_and_gradient(fz, fx);
+
+            w += a * Vec2::new(nx as f32, nz as f32);
+            dwx += a * f * Vec2::new(gx[0] as f32, gx[1] as f32);
+            dwz += a * f * Vec2::new(gz[1] as f32, gz[0] as f32);
+            amp += a;
+
+            fx *= self.lacunarity as f64;
+            fz *= self.lacunarity as f64;
+            f *= self.lacunarity;
+            a *= self.gain;
+        }
+
+        let k = self.warp_amp / amp;
+        let (w, dwx, dwz) = (w * k, dwx * k, dwz * k);
+        let (h, g) = self.source.height_and_gradient_world(p + w.as_dvec2());
+        // grad = J^T g with J = I + d(w)
+        let grad = Vec2::new(
+            g.x * (1.0 + dwx.x) + g.y * dwz.x,
+            g.x * dwx.y + g.y * (1.0 + dwz.y),
+        );
+        (h, grad)
+    }
+
+    fn sample_points(&self, points: &[DVec2], out: &mut [f32]) {
+        let (mut fx, mut fz) = noise_coords(points, self.warp_freq);
+        let mut w = vec![Vec2::ZERO; points.len()];
+        let mut a = 1.0;
+        let mut amp = 0.0;
+
+        // the whole displacement field octave by octave, then one batch for the source
+        for _ in 0..self.octaves {
+            for ((w, &x), &z) in w.iter_mut().zip(&fx).zip(&fz) {
+                *w += a * Vec2::new(
+                    self.perlin.get([x, z]) as f32,
+                    self.perlin.get([z, x]) as f32,
+                );
+            }
+            amp += a;
+            fx.iter_mut().for_each(|x| *x *= self.lacunarity as f64);
+            fz.iter_mut().for_each(|z| *z *= self.lacunarity as f64);
             a *= self.gain;
         }
-        
-        let wx = (sumx / amp) * self.warp_amp;
-        let wz = (sumz / amp) * self.warp_amp;
-        
-        self.source.height_at(x + wx, z + wz)
+
+        let warped: Vec<DVec2> = points
+            .iter()
+            .zip(&w)
+            .map(|(&p, &w)| p + (w / amp * self.warp_amp).as_dvec2())
+            .collect();
+        self.source.sample_points(&warped, out);
+    }
+
+    fn sample_points_with_gradient(&self, points: &[DVec2], out: &mut [f32], grad: &mut [Vec2]) {
+        let (mut fx, mut fz) = noise_coords(points, self.warp_freq);
+        let n = points.len();
+        let (mut w, mut dwx, mut dwz) = (
+            vec![Vec2::ZERO; n],
+            vec![Vec2::ZERO; n],
+            vec![Vec2::ZERO; n],
+        );
+        let mut a = 1.0;
+        let mut amp = 0.0;
+        let mut f = self.warp_freq;
+
+        for _ in 0..self.octaves {
+            for k in 0..n {
+                let (nx, gx) = self.perlin.value_and_gradient(fx[k], fz[k]);
+                let (nz, gz) = self.perlin.value_and_gradient(fz[k], fx[k]);
+                w[k] += a * Vec2::new(nx as f32, nz as f32);
+                dwx[k] += a * f * Vec2::new(gx[0] as f32, gx[1] as f32);
+                dwz[k] += a * f * Vec2::new(gz[1] as f32, gz[0] as f32);
+            }
+            amp += a;
+            fx.iter_mut().for_each(|x| *x *= self.lacunarity as f64);
+            fz.iter_mut().for_each(|z| *z *= self.lacunarity as f64);
+            f *= self.lacunarity;
+            a *= self.gain;
+        }
+
+        let k = self.warp_amp / amp;
+        let warped: Vec<DVec2> = points
+            .iter()
+            .zip(&w)
+            .map(|(&p, &w)| p + (w * k).as_dvec2())
+            .collect();
+        self.source.sample_points_with_gradient(&warped, out, grad);
+        for ((g, dwx), dwz) in grad[..n].iter_mut().zip(dwx).zip(dwz) {
+            let (dwx, dwz) = (dwx * k, dwz * k);
+            *g = Vec2::new(
+                g.x * (1.0 + dwx.x) + g.y * dwz.x,
+                g.x * dwx.y + g.y * (1.0 + dwz.y),
+            );
+        }
     }
 }
 
@@ -54,13 +163,157 @@ pub struct Oriented<S: HeightSource> {
     pub ortho_scale: f32,
 }
 
+impl<S: HeightSource> Oriented<S> {
+    /// (along `dir`, across it), scaled
+    fn project(&self, p: DVec2) -> DVec2 {
+        let t = self.dir.as_dvec2();
+        let n = DVec2::new(-t.y, -t.x);
+        DVec2::new(
+            p.dot(t) * self.main_scale as f64,
+            p.dot(n) * self.ortho_scale as f64,
+        )
+    }
+}
+
 impl<S: HeightSource> HeightSource for Oriented<S> {
-    fn height_at(&self, x: f32, z: f32) -> f32 {
-        let p = Vec2::new(x, z);
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        self.source.height_at_world(self.project(p))
+    }
+
+    fn height_and_gradient_world(&self, p: DVec2) -> (f32, Vec2) {
         let t = self.dir;
         let n = Vec2::new(-t.y, -t.x);
-        let u = p.dot(t) * self.main_scale;
-        let v = p.dot(n) * self.ortho_scale;
-        self.source.height_at(u, v)
+        let (h, g) = self.source.height_and_gradient_world(self.project(p));
+        (h, g.x * self.main_scale * t + g.y * self.ortho_scale * n)
     }
-}
\ No newline at end of file
+
+    fn sample_points(&self, points: &[DVec2], out: &mut [f32]) {
+        let projected: Vec<DVec2> = points.iter().map(|&p| self.project(p)).collect();
+        self.source.sample_points(&projected, out);
+    }
+
+    fn sample_points_with_gradient(&self, points: &[DVec2], out: &mut [f32], grad: &mut [Vec2]) {
+        let t = self.dir;
+        let n = Vec2::new(-t.y, -t.x);
+        let projected: Vec<DVec2> = points.iter().map(|&p| self.project(p)).collect();
+        self.source
+            .sample_points_with_gradient(&projected, out, grad);
+        for g in &mut grad[..points.len()] {
+            *g = g.x * self.main_scale * t + g.y * self.ortho_scale * n;
+        }
+    }
+}
+
+/// samples `source` with coordinates multiplied by `scale`
+pub struct Zoom<S: HeightSource> {
+    pub source: S,
+    pub scale: f64,
+}
+
+impl<S: HeightSource> HeightSource for Zoom<S> {
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        self.source.height_at_world(p * self.scale)
+    }
+
+    fn height_and_gradient_world(&self, p: DVec2) -> (f32, Vec2) {
+        let (h, g) = self.source.height_and_gradient_world(p * self.scale);
+        (h, g * self.scale as f32)
+    }
+
+    fn sample_points(&self, points: &[DVec2], out: &mut [f32]) {
+        let zoomed: Vec<DVec2> = points.iter().map(|&p| p * self.scale).collect();
+        self.source.sample_points(&zoomed, out);
+    }
+
+    fn sample_points_with_gradient(&self, points: &[DVec2], out: &mut [f32], grad: &mut [Vec2]) {
+        let zoomed: Vec<DVec2> = points.iter().map(|&p| p * self.scale).collect();
+        self.source.sample_points_with_gradient(&zoomed, out, grad);
+        for g in &mut grad[..points.len()] {
+            *g *= self.scale as f32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset::TerrainRecipe;
+    use crate::height::{grid_point, grid_points};
+
+    fn assert_batches_match(h: &dyn HeightSource) {
+        let (origin, spacing, nx, nz) = (DVec2::new(-1234.5, 987.25), 7.5, 9, 6);
+        let mut out = vec![0.0; nx * nz];
+        let mut with_grad = vec![0.0; nx * nz];
+        let mut grad = vec![Vec2::ZERO; nx * nz];
+        h.sample_grid(origin, spacing, nx, nz, &mut out);
+        h.sample_grid_with_gradient(origin, spacing, nx, nz, &mut with_grad, &mut grad);
+        for k in 0..nx * nz {
+            let p = grid_point(origin, spacing, nx, k);
+            let (v, g) = h.height_and_gradient_world(p);
+            assert_eq!(out[k], h.height_at_world(p), "height at {p}");
+            assert!(
+                (with_grad[k] - v).abs() < 1e-5,
+                "height with gradient at {p}"
+            );
+            assert!((grad[k] - g).length() < 1e-5, "{} vs {g} at {p}", grad[k]);
+        }
+
+        // scattered points take the same path
+        let points: Vec<DVec2> = grid_points(origin, spacing, nx, nz)
+            .into_iter()
+            .map(|p| p * DVec2::new(1.7, -0.6))
+            .collect();
+        let mut scattered = vec![0.0; points.len()];
+        h.sample_points(&points, &mut scattered);
+        for (&p, s) in points.iter().zip(scattered) {
+            assert_eq!(s, h.height_at_world(p));
+        }
+    }
+
+    #[test]
+    fn shipped_recipe_batches_through_the_warp() {
+        // Amp -> Bias -> Freq -> Warp2D -> Add2(PerlinFbm, AlongLineDir(PerlinRidged))
+        let text = include_str!("../../../../assets/terrain/europa.height.ron");
+        let recipe: TerrainRecipe = ron::from_str(text).unwrap();
+        assert_batches_match(
+            recipe
+                .height
+                .build(&crate::TerrainParams::europa_demo())
+                .unwrap()
+                .as_ref(),
+        );
+    }
+
+    #[test]
+    fn warp_oriented_and_zoom_batch_like_points() {
+        let fbm = || crate::height::noise::PerlinFbm {
+            noise: Perlin::new(3),
+            fractal: crate::height::noise::Fractal {
+                freq: 1.0 / 300.0,
+                octaves: 4,
+                lacunarity: 2.0,
+                gain: 0.5,
+                amplitude: 10.0,
+            },
+        };
+        assert_batches_match(&Warp2D {
+            source: fbm(),
+            perlin: Perlin::new(8),
+            warp_amp: 60.0,
+            warp_freq: 1.0 / 900.0,
+            octaves: 3,
+            lacunarity: 2.1,
+            gain: 0.55,
+        });
+        assert_batches_match(&Oriented {
+            source: fbm(),
+            dir: Vec2::new(0.6, 0.8),
+            main_scale: 1.0,
+            ortho_scale: 0.35,
+        });
+        assert_batches_match(&Zoom {
+            source: fbm(),
+            scale: 0.25,
+        });
+    }
+}
@@ -1,103 +1,151 @@
 use bevy::prelude::*;
+use bevy::transform::TransformSystems;
+mod asset;
+mod chunks;
+pub mod export;
+mod generator;
+pub mod globe;
+mod grid;
 mod height;
+mod lod;
 mod mesh;
+mod origin;
 mod params;
+mod raycast;
+mod rtin;
+mod sampler;
 mod systems;
 
-pub use height::{HeightFn, HeightSource, arc, comb, noise, warp};
-pub use params::TerrainParams;
+use asset::RecipeHandle;
+pub use asset::{RecipeError, TerrainRecipe, TerrainRecipeLoader};
+pub use generator::{HeightGenerator, europa_height};
+pub use grid::Heightfield;
+pub use height::{
+    HeightFn, HeightSource, arc, bands, chaos, comb, craters, curvature, dem, lenticulae, noise,
+    recipe, ridges, warp,
+};
+pub use lod::TerrainLod;
+pub use mesh::{EdgeMode, build_europa_mesh};
+pub use origin::{FloatingOriginPlugin, NoRebase, RebaseSet, Rebased, WorldOrigin};
+pub use params::{EUROPA_RADIUS, TerrainParams};
+pub use raycast::{TerrainHit, TerrainPicked, TerrainRaycast, raycast_height};
+pub use rtin::{AdaptiveStats, build_adaptive_mesh, build_europa_mesh_adaptive};
+pub use sampler::{TerrainSample, TerrainSampler};
 
 #[derive(Clone)]
 pub struct TerrainPlugin {
     pub params: TerrainParams,
     pub height: HeightFn,
+    pub lod: TerrainLod,
+    /// asset path of a `TerrainRecipe`; once loaded it replaces `height`
+    pub recipe: Option<String>,
+    /// rebuilds `height` from `TerrainParams` when they change (unless a recipe is set)
+    pub generator: Option<HeightGenerator>,
 }
 
 impl TerrainPlugin {
     pub fn with(params: TerrainParams, height: HeightFn) -> Self {
-        Self { params, height }
+        Self {
+            params,
+            height,
+            lod: TerrainLod::default(),
+            recipe: None,
+            generator: None,
+        }
     }
 
-    pub fn europa_default() -> Self {
-        use crate::height::arc;
-        use crate::height::noise::{PerlinFbm, PerlinRidged};
-        use comb::Bias;
-        use noise::Perlin;
-        use warp::Warp2D;
-
-        let seed = 1337;
-        let base = PerlinFbm {
-            perlin: Perlin::new(seed),
-            freq: 1.0 / 600.0,
-            octaves: 5,
-            lacunarity: 2.0,
-            gain: 0.5,
-            amplitude: 1.0,
-        };
-
-        // anisotropic ridges aligned by an oriented wrapper later
-        let ridged = PerlinRidged {
-            perlin: Perlin::new(seed ^ 0xB529_7A4D),
-            freq: (1.0 / 600.0) * 2.5,
-            octaves: 4,
-            lacunarity: 2.2,
-            gain: 0.75,
-            amplitude: 1.0,
-            z_anisotropy: 2.0,
-        };
-
-        // orient ridges along line_dir
-        let line_dir = Vec2::new(0.8, 0.2).normalize();
-        let oriented = warp::Oriented {
-            source: ridged,
-            dir: line_dir,
-            main_scale: 1.0,
-            ortho_scale: 0.35,
-        };
-
-        let warp = Warp2D {
-            source: comb::Add2 {
-                a: base,
-                b: oriented,
-            },
-            perlin: Perlin::new(seed ^ 0x9E37_79B9),
-            warp_amp: 40.0,
-            warp_freq: (1.0 / 600.0) * 0.6,
-            octaves: 3,
-            lacunarity: 2.1,
-            gain: 0.55,
-        };
-
-        let combined = Bias {
-            s: comb::Scale {
-                s: warp,
-                scale: 1.0,
-            },
-            bias: -0.1,
-        };
-        let params = TerrainParams {
-            amp: 12.0,
-            size: 3000.0,
-            res: 512,
-            freq: 1.0 / 600.0,
-            line_dir,
-            seed,
-        };
+    /// follow a recipe file instead of the Rust-side graph. `height` stays up
+    /// until the recipe loads, and keeps the terrain alive if it never does.
+    /// the recipe is rebuilt when `TerrainParams` change, and the nodes that
+    /// read them (`Generator`, `Amp`, `Freq`, `AlongLineDir`) follow
+    pub fn with_recipe(mut self, path: impl Into<String>) -> Self {
+        self.recipe = Some(path.into());
+        self
+    }
 
+    /// terrain regenerated from `params` by `generator` whenever the resource changes
+    pub fn from_params(params: TerrainParams, generator: HeightGenerator) -> Self {
         Self {
             params,
-            height: arc(combined),
+            height: generator(&params),
+            lod: TerrainLod::default(),
+            recipe: None,
+            generator: Some(generator),
         }
     }
+
+    /// bend the patch onto a sphere of `radius` (`EUROPA_RADIUS` for the real horizon)
+    pub fn curved(mut self, radius: f32) -> Self {
+        self.params.curvature = Some(radius);
+        self
+    }
+
+    pub fn europa_default() -> Self {
+        Self::from_params(TerrainParams::europa_demo(), europa_height)
+    }
 }
 
+/// the surface as rendered and queried: `BaseHeight` after `TerrainParams::surface`
 #[derive(Resource)]
 struct HeightResource(pub HeightFn);
 
+/// the height graph before curvature, from the plugin, a recipe or the generator
+#[derive(Resource)]
+struct BaseHeight(pub HeightFn);
+
+#[derive(Resource)]
+struct TerrainGenerator(pub HeightGenerator);
+
 impl Plugin for TerrainPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(self.params)
-            .insert_resource(HeightResource(self.height.clone()))
-            .add_systems(Startup, systems::spawn_europa);
+            .insert_resource(self.lod)
+            .insert_resource(BaseHeight(self.height.clone()))
+            .insert_resource(HeightResource(self.params.surface(self.height.clone())))
+            .init_resource::<WorldOrigin>()
+            .init_asset::<TerrainRecipe>()
+            .init_asset_loader::<TerrainRecipeLoader>()
+            .add_systems(Startup, systems::spawn_europa)
+            .add_message::<raycast::TerrainPicked>()
+            .add_systems(
+                Update,
+                (
+                    systems::apply_curvature.before(systems::stream_chunks),
+                    systems::stream_chunks,
+                    raycast::pick_terrain,
+                ),
+            )
+            .add_systems(
+                PostUpdate,
+                systems::follow_origin
+                    .run_if(resource_changed::<WorldOrigin>)
+                    .after(RebaseSet)
+                    .before(TransformSystems::Propagate),
+            );
+
+        if let Some(generator) = self.generator {
+            app.insert_resource(TerrainGenerator(generator))
+                .add_systems(
+                    Update,
+                    systems::regenerate_height
+                        .run_if(not(resource_exists::<RecipeHandle>))
+                        .before(systems::apply_curvature),
+                );
+        }
+
+        if let Some(path) = self.recipe.clone() {
+            app.add_systems(
+                Startup,
+                move |mut commands: Commands, server: Res<AssetServer>| {
+                    commands.insert_resource(RecipeHandle(server.load(path.clone())));
+                },
+            )
+            .add_systems(
+                Update,
+                systems::apply_recipe
+                    .run_if(resource_exists::<RecipeHandle>)
+                    .before(systems::apply_curvature),
+            );
+        }
     }
 }
@@ -0,0 +1,180 @@
+use bevy::math::{DVec2, DVec3};
+use bevy::prelude::*;
+
+use crate::chunks::ChunkBudget;
+use crate::mesh::EdgeMode;
+
+/// quadtree streaming settings for the chunked terrain
+#[derive(Resource, Clone, Copy)]
+pub struct TerrainLod {
+    /// edge length of a root (depth 0) chunk in meters
+    pub root_size: f32,
+    /// quads per chunk side, the same at every depth
+    pub chunk_res: u32,
+    /// deepest quadtree level
+    pub max_depth: u8,
+    /// a node splits while the camera is closer than `size * split_factor`
+    pub split_factor: f32,
+    /// root chunks kept around the camera in each direction
+    pub root_radius: i32,
+    /// full-resolution chunk builds started per frame
+    pub builds_per_frame: usize,
+    /// placeholder meshes built per frame. they're built on the spot, so a
+    /// big jump doesn't stall one frame on hundreds of them
+    pub placeholders_per_frame: usize,
+    /// cap on chunk builds running in the background at once
+    pub max_in_flight: usize,
+    /// quads per side of the placeholder shown while a chunk builds
+    pub placeholder_res: u32,
+    /// skirt depth as a fraction of chunk size, 0 disables skirts
+    pub skirt_ratio: f32,
+}
+
+impl Default for TerrainLod {
+    fn default() -> Self {
+        Self {
+            root_size: 6000.0,
+            chunk_res: 64,
+            max_depth: 6,
+            split_factor: 1.5,
+            root_radius: 3,
+            builds_per_frame: 4,
+            placeholders_per_frame: 16,
+            max_in_flight: 16,
+            placeholder_res: 8,
+            skirt_ratio: 0.02,
+        }
+    }
+}
+
+impl TerrainLod {
+    pub(crate) fn budget(&self) -> ChunkBudget {
+        ChunkBudget {
+            placeholders_per_frame: self.placeholders_per_frame,
+            builds_per_frame: self.builds_per_frame,
+            max_in_flight: self.max_in_flight,
+        }
+    }
+}
+
+/// address of a quadtree node: depth plus integer tile coords at that depth
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub(crate) struct ChunkKey {
+    pub depth: u8,
+    pub x: i32,
+    pub z: i32,
+}
+
+impl ChunkKey {
+    pub fn size(&self, lod: &TerrainLod) -> f32 {
+        lod.root_size / (1u32 << self.depth) as f32
+    }
+
+    /// world xz of the chunk's min corner, in f64 so far-out neighbours
+    /// still share their edges exactly
+    pub fn origin(&self, lod: &TerrainLod) -> DVec2 {
+        let s = self.size(lod) as f64;
+        DVec2::new(self.x as f64 * s, self.z as f64 * s)
+    }
+
+    pub fn center(&self, lod: &TerrainLod) -> DVec2 {
+        self.origin(lod) + DVec2::splat(self.size(lod) as f64 * 0.5)
+    }
+
+    fn children(&self) -> [ChunkKey; 4] {
+        let (d, x, z) = (self.depth + 1, self.x * 2, self.z * 2);
+        [
+            ChunkKey { depth: d, x, z },
+            ChunkKey {
+                depth: d,
+                x: x + 1,
+                z,
+            },
+            ChunkKey {
+                depth: d,
+                x,
+                z: z + 1,
+            },
+            ChunkKey {
+                depth: d,
+                x: x + 1,
+                z: z + 1,
+            },
+        ]
+    }
+
+    pub fn edges(&self, lod: &TerrainLod) -> EdgeMode {
+        if lod.skirt_ratio > 0.0 {
+            EdgeMode::Skirts {
+                depth: self.size(lod) * lod.skirt_ratio,
+            }
+        } else {
+            EdgeMode::Open
+        }
+    }
+
+    /// distance from `eye` (world) to the chunk's footprint on the datum: the
+    /// plane y = 0, or with `curvature` the sphere it is bent onto, which has
+    /// dropped by the sagitta under the footprint's nearest point (290 m 30 km
+    /// out on Europa)
+    pub fn distance(&self, lod: &TerrainLod, eye: DVec3, curvature: Option<f32>) -> f32 {
+        let min = self.origin(lod);
+        let max = min + DVec2::splat(self.size(lod) as f64);
+        let p = DVec2::new(eye.x, eye.z);
+        let near = p.clamp(min, max);
+        let sag = curvature.map_or(0.0, |r| {
+            // R (1 - cos θ) as in `curvature::bend`
+            let r = r as f64;
+            2.0 * r * (0.5 * near.length() / r).sin().powi(2)
+        });
+        let d = near - p;
+        DVec3::new(d.x, eye.y + sag, d.y).length() as f32
+    }
+}
+
+/// leaf chunks of the quadtree around `eye`, split by `ChunkKey::distance`
+pub(crate) fn select_chunks(lod: &TerrainLod, eye: DVec3, curvature: Option<f32>) -> Vec<ChunkKey> {
+    let rx = (eye.x / lod.root_size as f64).floor() as i32;
+    let rz = (eye.z / lod.root_size as f64).floor() as i32;
+
+    let mut leaves = Vec::new();
+    let mut stack = Vec::new();
+    for z in rz - lod.root_radius..=rz + lod.root_radius {
+        for x in rx - lod.root_radius..=rx + lod.root_radius {
+            stack.push(ChunkKey { depth: 0, x, z });
+        }
+    }
+
+    while let Some(key) = stack.pop() {
+        let split = key.depth < lod.max_depth
+            && key.distance(lod, eye, curvature) < key.size(lod) * lod.split_factor;
+        if split {
+            stack.extend(key.children());
+        } else {
+            leaves.push(key);
+        }
+    }
+    leaves
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curved_chunks_count_the_drop_below_the_eye() {
+        let lod = TerrainLod::default();
+        // a root chunk 30 km out, seen from 2 m above the origin
+        let key = ChunkKey {
+            depth: 0,
+            x: 5,
+            z: 0,
+        };
+        let eye = DVec3::new(0.0, 2.0, 0.0);
+        let flat = key.distance(&lod, eye, None);
+        let curved = key.distance(&lod, eye, Some(crate::EUROPA_RADIUS));
+        assert!((flat - 30_000.0).abs() < 0.01, "{flat}");
+        let drop = (curved.powi(2) - 30_000.0_f32.powi(2)).sqrt() - 2.0;
+        assert!((drop - 288.3).abs() < 0.5, "sagitta {drop}");
+    }
+}
@@ -1,34 +1,113 @@
+use bevy::math::DVec2;
 use bevy::mesh::{Indices, PrimitiveTopology};
 use bevy::prelude::*;
 
 use crate::height::HeightSource;
 use crate::params::TerrainParams;
 
-pub(crate) fn build_europa_mesh(p: TerrainParams, height: &dyn HeightSource) -> Mesh {
-    let n = p.res; // quads per side
+/// how a patch treats its outer edge where it meets a neighbour
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EdgeMode {
+    /// plain grid, nothing added past the border
+    Open,
+    /// vertical curtain hanging `depth` meters below the border, hides
+    /// cracks against neighbours built at a different resolution
+    Skirts { depth: f32 },
+}
+
+/// the params footprint centred on the origin, sampled from `height` as given:
+/// pass `p.surface(base)` for the curved surface, the plugin's rendered height
+/// is already bent. a one-off build (CLI, export), so the rows are sampled on
+/// every core
+pub fn build_europa_mesh(p: TerrainParams, height: &dyn HeightSource) -> Mesh {
+    let half = p.size * 0.5;
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    build_patch(
+        DVec2::splat(-half as f64),
+        p.size,
+        p.res,
+        EdgeMode::Open,
+        height,
+        threads,
+        &|| false,
+    )
+    .expect("build without cancellation always finishes")
+}
+
+/// square grid patch with its min corner at `origin` (world xz) and `n` quads per side.
+/// positions are relative to the patch centre
+pub(crate) fn build_patch_mesh(
+    origin: DVec2,
+    size: f32,
+    n: u32,
+    edges: EdgeMode,
+    height: &dyn HeightSource,
+) -> Mesh {
+    build_patch_mesh_until(origin, size, n, edges, height, &|| false)
+        .expect("build without cancellation always finishes")
+}
+
+/// `build_patch_mesh` that gives up with `None` once `cancelled` turns true.
+/// checked between row bands, so a dropped build stops within one band
+pub(crate) fn build_patch_mesh_until(
+    origin: DVec2,
+    size: f32,
+    n: u32,
+    edges: EdgeMode,
+    height: &dyn HeightSource,
+    cancelled: &(dyn Fn() -> bool + Sync),
+) -> Option<Mesh> {
+    build_patch(origin, size, n, edges, height, 1, cancelled)
+}
+
+fn build_patch(
+    origin: DVec2,
+    size: f32,
+    n: u32,
+    edges: EdgeMode,
+    height: &dyn HeightSource,
+    threads: usize,
+    cancelled: &(dyn Fn() -> bool + Sync),
+) -> Option<Mesh> {
     let v_count = (n + 1) as usize; // vertices per side
-    let size = p.size;
     let half = size * 0.5;
     let dx = size / n as f32;
 
-    // precompute heights
-    let mut heights = vec![0.0_f32; v_count * v_count];
-    for j in 0..v_count {
-        for i in 0..v_count {
-            let x = -half + i as f32 * dx;
-            let z = -half + j as f32 * dx;
-            heights[j * v_count + i] = height.height_at(x, z);
-        }
+    // normals come straight from the source's gradient, so patch borders
+    // agree with their neighbours without sampling past the edge
+    let count = v_count * v_count;
+    let mut heights = vec![0.0; count];
+    let mut grads = vec![Vec2::ZERO; count];
+    sample_rows(
+        height,
+        origin,
+        dx,
+        v_count,
+        &mut heights,
+        &mut grads,
+        threads,
+        cancelled,
+    );
+    if cancelled() {
+        return None;
     }
 
-    let mut positions = Vec::with_capacity(v_count * v_count);
-    let mut uvs = Vec::with_capacity(v_count * v_count);
+    let mut positions = Vec::with_capacity(count);
+    let mut normals = Vec::with_capacity(count);
+    let mut uvs = Vec::with_capacity(count);
     for j in 0..v_count {
         for i in 0..v_count {
+            let k = j * v_count + i;
             let x = -half + i as f32 * dx;
             let z = -half + j as f32 * dx;
-            let y = heights[j * v_count + i];
-            positions.push([x, y, z]);
+            positions.push([x, heights[k], z]);
+
+            let g = grads[k];
+            let mut normal = Vec3::new(-g.x, 1.0, -g.y).normalize();
+            if !normal.is_finite() {
+                normal = Vec3::Y;
+            }
+            normals.push(normal.to_array());
             uvs.push([i as f32 / n as f32, j as f32 / n as f32]);
         }
     }
@@ -44,23 +123,15 @@ pub(crate) fn build_europa_mesh(p: TerrainParams, height: &dyn HeightSource) ->
         }
     }
 
-    let mut normals = vec![[0.0, 1.0, 0.0]; v_count * v_count];
-    for j in 0..v_count {
-        for i in 0..v_count {
-            let h_l = heights[j * v_count + i.saturating_sub(1)];
-            let h_r = heights[j * v_count + (i + 1).min(v_count - 1)];
-            let h_d = heights[j.saturating_sub(1) * v_count + i];
-            let h_u = heights[(j + 1).min(v_count - 1) * v_count + i];
-
-            let dh_dx = (h_r - h_l) / (2.0 * dx);
-            let dh_dz = (h_u - h_d) / (2.0 * dx);
-
-            let mut n = Vec3::new(-dh_dx, 1.0, -dh_dz).normalize();
-            if !n.is_finite() {
-                n = Vec3::Y;
-            }
-            normals[j * v_count + i] = n.to_array();
-        }
+    if let EdgeMode::Skirts { depth } = edges {
+        add_skirts(
+            n,
+            &|[x, y, z]| [x, y - depth, z],
+            &mut positions,
+            &mut normals,
+            &mut uvs,
+            &mut indices,
+        );
     }
 
     let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, default());
@@ -68,8 +139,87 @@ pub(crate) fn build_europa_mesh(p: TerrainParams, height: &dyn HeightSource) ->
     mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
     mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
     mesh.insert_indices(Indices::U32(indices));
-    if !mesh.morph_targets().is_none() {
+    if mesh.morph_targets().is_some() {
         mesh.generate_tangents().ok();
     }
-    mesh
+    Some(mesh)
+}
+
+/// samples a `v` x `v` grid in bands of rows. with `threads` > 1 the bands go
+/// to scoped OS threads; only for builds outside bevy's pools, since chunk
+/// builds already are pool tasks and blocking one on a nested scope would stall
+/// the pool
+#[allow(clippy::too_many_arguments)]
+fn sample_rows(
+    height: &dyn HeightSource,
+    origin: DVec2,
+    dx: f32,
+    v: usize,
+    heights: &mut [f32],
+    grads: &mut [Vec2],
+    threads: usize,
+    cancelled: &(dyn Fn() -> bool + Sync),
+) {
+    // at least a handful of bands, so cancellation is noticed mid-build
+    let bands = (threads * 2).max(8).clamp(1, v);
+    let rows = v.div_ceil(bands);
+    let band = |b: usize, h: &mut [f32], g: &mut [Vec2]| {
+        if cancelled() {
+            return;
+        }
+        let first = b * rows;
+        let band_origin = origin + DVec2::new(0.0, first as f64 * dx as f64);
+        height.sample_grid_with_gradient(band_origin, dx, v, h.len() / v, h, g);
+    };
+
+    let bands = heights
+        .chunks_mut(rows * v)
+        .zip(grads.chunks_mut(rows * v))
+        .enumerate();
+    if threads <= 1 {
+        bands.for_each(|(b, (h, g))| band(b, h, g));
+        return;
+    }
+    let band = &band;
+    std::thread::scope(|scope| {
+        for (b, (h, g)) in bands {
+            scope.spawn(move || band(b, h, g));
+        }
+    });
+}
+
+/// drops a copy of the border loop (each vertex moved by `lower`) and stitches
+/// it to the edge with outward-facing quads. skirt vertices reuse the edge
+/// normal/uv so shading doesn't change where they peek out
+pub(crate) fn add_skirts(
+    n: u32,
+    lower: &dyn Fn([f32; 3]) -> [f32; 3],
+    positions: &mut Vec<[f32; 3]>,
+    normals: &mut Vec<[f32; 3]>,
+    uvs: &mut Vec<[f32; 2]>,
+    indices: &mut Vec<u32>,
+) {
+    let idx = |i: u32, j: u32| j * (n + 1) + i;
+
+    // walk the border so that (next - here) x -Y points outward
+    let mut ring = Vec::with_capacity((4 * n) as usize);
+    ring.extend((0..n).map(|i| idx(i, 0)));
+    ring.extend((0..n).map(|j| idx(n, j)));
+    ring.extend((1..=n).rev().map(|i| idx(i, n)));
+    ring.extend((1..=n).rev().map(|j| idx(0, j)));
+
+    let base = positions.len() as u32;
+    for &v in &ring {
+        positions.push(lower(positions[v as usize]));
+        normals.push(normals[v as usize]);
+        uvs.push(uvs[v as usize]);
+    }
+
+    let len = ring.len() as u32;
+    for k in 0..len {
+        let next = (k + 1) % len;
+        let (a, b) = (ring[k as usize], ring[next as usize]);
+        let (a_low, b_low) = (base + k, base + next);
+        indices.extend_from_slice(&[a, b, a_low, b, b_low, a_low]);
+    }
 }
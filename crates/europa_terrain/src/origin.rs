@@ -0,0 +1,159 @@
+use bevy::math::{DVec2, DVec3};
+use bevy::prelude::*;
+use bevy::transform::TransformSystems;
+
+use crate::height::HeightSource;
+
+/// world position of the render-space origin. transforms are f32 and relative
+/// to it (`world = origin + translation`), so they keep their precision however
+/// far the camera goes. stays at zero without `FloatingOriginPlugin`
+#[derive(Resource, Clone, Copy, Default, Debug, PartialEq)]
+pub struct WorldOrigin(pub DVec3);
+
+impl WorldOrigin {
+    pub fn to_world(&self, p: Vec3) -> DVec3 {
+        self.0 + p.as_dvec3()
+    }
+
+    pub fn to_render(&self, p: DVec3) -> Vec3 {
+        (p - self.0).as_vec3()
+    }
+
+    /// world xz of render-space `(x, z)`
+    pub fn world_xz(&self, x: f32, z: f32) -> DVec2 {
+        DVec2::new(self.0.x + x as f64, self.0.z + z as f64)
+    }
+}
+
+/// root entity that rebasing leaves alone because it places itself from
+/// `WorldOrigin` (the terrain and globe chunks do)
+#[derive(Component, Clone, Copy, Default, Debug)]
+pub struct NoRebase;
+
+/// the rebase step, in `PostUpdate` before transform propagation. systems that
+/// place entities from `WorldOrigin` run after it
+#[derive(SystemSet, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct RebaseSet;
+
+/// moves the origin to the camera once it strays `threshold` meters from it,
+/// shifting every root entity back by the same amount
+#[derive(Clone, Copy)]
+pub struct FloatingOriginPlugin {
+    pub threshold: f32,
+}
+
+impl Default for FloatingOriginPlugin {
+    fn default() -> Self {
+        Self { threshold: 2_000.0 }
+    }
+}
+
+#[derive(Resource)]
+struct RebaseThreshold(f32);
+
+impl Plugin for FloatingOriginPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WorldOrigin>()
+            .insert_resource(RebaseThreshold(self.threshold))
+            .configure_sets(PostUpdate, RebaseSet.before(TransformSystems::Propagate))
+            .add_systems(PostUpdate, rebase.in_set(RebaseSet));
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn rebase(
+    threshold: Res<RebaseThreshold>,
+    mut origin: ResMut<WorldOrigin>,
+    mut roots: Query<(&mut Transform, Has<Camera3d>), (Without<ChildOf>, Without<NoRebase>)>,
+) {
+    let Some(eye) = roots
+        .iter()
+        .find(|(_, cam)| *cam)
+        .map(|(t, _)| t.translation)
+    else {
+        return;
+    };
+    if eye.length() < threshold.0 {
+        return;
+    }
+    origin.0 += eye.as_dvec3();
+    for (mut t, _) in &mut roots {
+        t.translation -= eye;
+    }
+}
+
+/// `source` seen from render space: positions and heights relative to `origin`.
+/// what raycasts and other render-space queries sample
+pub struct Rebased<S: HeightSource> {
+    pub source: S,
+    pub origin: DVec3,
+}
+
+impl<S: HeightSource> HeightSource for Rebased<S> {
+    fn height_at_world(&self, p: DVec2) -> f32 {
+        let h = self.source.height_at_world(self.world(p));
+        (h as f64 - self.origin.y) as f32
+    }
+
+    fn height_and_gradient_world(&self, p: DVec2) -> (f32, Vec2) {
+        let (h, grad) = self.source.height_and_gradient_world(self.world(p));
+        ((h as f64 - self.origin.y) as f32, grad)
+    }
+}
+
+impl<S: HeightSource> Rebased<S> {
+    fn world(&self, p: DVec2) -> DVec2 {
+        p + DVec2::new(self.origin.x, self.origin.z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::height::chaos::ChaosTerrain;
+    use crate::height::craters::Craters;
+    use crate::height::noise::{Fractal, Perlin, PerlinFbm};
+
+    fn fbm() -> PerlinFbm {
+        PerlinFbm {
+            noise: Perlin::new(11),
+            fractal: Fractal {
+                freq: 1.0 / 600.0,
+                octaves: 5,
+                lacunarity: 2.0,
+                gain: 0.5,
+                amplitude: 12.0,
+            },
+        }
+    }
+
+    /// 5000 km out f32 positions are half a meter apart; through an origin
+    /// the same walk in 5 cm steps has no stairs in it
+    fn assert_smooth<S: HeightSource>(source: S, origin: DVec3) {
+        let local = Rebased { source, origin };
+        let heights: Vec<f32> = (0..40)
+            .map(|k| local.height_at(k as f32 * 0.05, 0.0))
+            .collect();
+        for w in heights.windows(3) {
+            assert!((w[0] - 2.0 * w[1] + w[2]).abs() < 1e-3, "{w:?}");
+        }
+        let world = local
+            .source
+            .height_at_world(DVec2::new(origin.x + 1.0, origin.z));
+        assert_eq!(local.height_at(1.0, 0.0), world);
+    }
+
+    #[test]
+    fn far_out_terrain_stays_smooth() {
+        assert_smooth(fbm(), DVec3::new(5.0e6, 0.0, -3.0e6));
+    }
+
+    #[test]
+    fn far_out_geology_stays_smooth() {
+        // rafts carry the fbm, so a raft interior walks like the fbm itself
+        let chaos = ChaosTerrain::europa(fbm(), 4);
+        assert_smooth(chaos, DVec3::new(5.0e6, 0.0, -3.0e6));
+        // down the bowl of a 20 m crater
+        assert_smooth(Craters::europa(4), DVec3::new(5_011_850.0, 0.0, -3.0e6));
+    }
+}
@@ -1,5 +1,11 @@
 use bevy::prelude::*;
 
+use crate::height::curvature::Curved;
+use crate::height::{HeightFn, arc};
+
+/// mean radius of Europa in meters
+pub const EUROPA_RADIUS: f32 = 1_560_800.0;
+
 #[derive(Resource, Clone, Copy)]
 pub struct TerrainParams {
     /// world size in meters
@@ -14,6 +20,12 @@ pub struct TerrainParams {
     pub line_dir: Vec2,
     /// rng seed
     pub seed: u32,
+    /// bend the surface onto a sphere of this radius touching the datum at the
+    /// world origin, `None` keeps it flat. positions stay on the flat xz grid,
+    /// which drifts by `d³ / 6R²` (70 m 100 km out on Europa), so keep the
+    /// streamed area and the camera within about 100 km of the origin and use
+    /// the globe beyond that
+    pub curvature: Option<f32>,
 }
 
 impl TerrainParams {
@@ -24,7 +36,20 @@ impl TerrainParams {
             amp: 12.0,
             freq: 1.0 / 600.0,
             line_dir: Vec2::new(0.8, 0.2).normalize(),
-            seed: 1337
+            seed: 1337,
+            curvature: None,
+        }
+    }
+
+    /// `height` as it should be rendered and queried, bent if `curvature` is set
+    pub fn surface(&self, height: HeightFn) -> HeightFn {
+        match self.curvature {
+            Some(radius) => arc(Curved {
+                source: height,
+                radius,
+                center: Vec2::ZERO,
+            }),
+            None => height,
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,163 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::height::HeightSource;
+use crate::sampler::TerrainSampler;
+
+/// shortest and longest march step, meters. steps shrink with the gap to the
+/// ground, so features thinner than the shortest step can be stepped over
+const MIN_STEP: f32 = 0.25;
+const MAX_STEP: f32 = 50.0;
+/// bisection rounds once the ray is bracketed, ~1e-6 of the last step
+const REFINE_STEPS: u32 = 20;
+/// cursor reach for projections that don't say where their far plane is
+const DEFAULT_REACH: f32 = 50_000.0;
+
+/// where a ray meets the ground
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TerrainHit {
+    pub position: Vec3,
+    pub normal: Vec3,
+    /// along the ray from its origin, meters
+    pub distance: f32,
+}
+
+/// marches `ray` over `height` until it dips below the surface, then bisects
+/// the last step. `None` if it starts underground or stays above for `max_distance`
+pub fn raycast_height(
+    height: &dyn HeightSource,
+    ray: Ray3d,
+    max_distance: f32,
+) -> Option<TerrainHit> {
+    let gap = |t: f32| {
+        let p = ray.get_point(t);
+        p.y - height.height_at(p.x, p.z)
+    };
+
+    let mut t0 = 0.0;
+    let mut g0 = gap(t0);
+    if g0 < 0.0 {
+        return None;
+    }
+    while t0 < max_distance {
+        let t1 = (t0 + (0.5 * g0).clamp(MIN_STEP, MAX_STEP)).min(max_distance);
+        let g1 = gap(t1);
+        if g1 <= 0.0 {
+            let (mut lo, mut hi) = (t0, t1);
+            for _ in 0..REFINE_STEPS {
+                let mid = 0.5 * (lo + hi);
+                if gap(mid) > 0.0 {
+                    lo = mid;
+                } else {
+                    hi = mid;
+                }
+            }
+            let p = ray.get_point(hi);
+            let (h, grad) = height.height_and_gradient(p.x, p.z);
+            let mut normal = Vec3::new(-grad.x, 1.0, -grad.y).normalize();
+            if !normal.is_finite() {
+                normal = Vec3::Y;
+            }
+            return Some(TerrainHit {
+                position: Vec3::new(p.x, h, p.z),
+                normal,
+                distance: hi,
+            });
+        }
+        (t0, g0) = (t1, g1);
+    }
+    None
+}
+
+/// terrain raycasts, from arbitrary rays or from the cursor through the 3D
+/// camera. rays and hits are in render space, like the camera transform
+#[derive(SystemParam)]
+pub struct TerrainRaycast<'w, 's> {
+    pub sampler: TerrainSampler<'w>,
+    cameras: Query<
+        'w,
+        's,
+        (
+            &'static Camera,
+            &'static GlobalTransform,
+            &'static Projection,
+        ),
+        With<Camera3d>,
+    >,
+    windows: Query<'w, 's, &'static Window, With<PrimaryWindow>>,
+}
+
+impl TerrainRaycast<'_, '_> {
+    pub fn ray(&self, ray: Ray3d, max_distance: f32) -> Option<TerrainHit> {
+        raycast_height(&self.sampler.rebased(), ray, max_distance)
+    }
+
+    /// ray from the camera through the cursor, `None` while it's outside the window
+    pub fn cursor_ray(&self) -> Option<Ray3d> {
+        let cursor = self.windows.single().ok()?.cursor_position()?;
+        let (camera, transform, _) = self.cameras.single().ok()?;
+        camera.viewport_to_world(transform, cursor).ok()
+    }
+
+    /// what the cursor is over, out to the camera's far plane
+    pub fn cursor_hit(&self) -> Option<TerrainHit> {
+        let (_, _, projection) = self.cameras.single().ok()?;
+        let far = match projection {
+            Projection::Perspective(p) => p.far,
+            Projection::Orthographic(o) => o.far,
+            _ => DEFAULT_REACH,
+        };
+        self.ray(self.cursor_ray()?, far)
+    }
+}
+
+/// sent when the ground is clicked with the left mouse button
+#[derive(Message, Clone, Copy, Debug)]
+pub struct TerrainPicked {
+    pub hit: TerrainHit,
+}
+
+pub(crate) fn pick_terrain(
+    buttons: Res<ButtonInput<MouseButton>>,
+    raycast: TerrainRaycast,
+    mut picked: MessageWriter<TerrainPicked>,
+) {
+    if !buttons.just_pressed(MouseButton::Left) {
+        return;
+    }
+    if let Some(hit) = raycast.cursor_hit() {
+        picked.write(TerrainPicked { hit });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::height::noise::{Fractal, Perlin, PerlinFbm};
+
+    #[test]
+    fn hit_lies_on_the_surface() {
+        let ground = PerlinFbm {
+            noise: Perlin::new(5),
+            fractal: Fractal {
+                freq: 1.0 / 80.0,
+                octaves: 4,
+                lacunarity: 2.0,
+                gain: 0.5,
+                amplitude: 10.0,
+            },
+        };
+        let ray = Ray3d::new(
+            Vec3::new(0.0, 60.0, 0.0),
+            Dir3::new(Vec3::new(1.0, -0.3, 0.4)).unwrap(),
+        );
+        let hit = raycast_height(&ground, ray, 5_000.0).unwrap();
+        assert!((hit.position.y - ground.height_at(hit.position.x, hit.position.z)).abs() < 1e-3);
+        assert!(hit.position.distance(ray.get_point(hit.distance)) < 1e-2);
+        assert!(hit.normal.y > 0.0);
+
+        let up = Ray3d::new(Vec3::new(0.0, 60.0, 0.0), Dir3::Y);
+        assert!(raycast_height(&ground, up, 5_000.0).is_none());
+    }
+}
@@ -0,0 +1,283 @@
+use bevy::mesh::{Indices, PrimitiveTopology};
+use bevy::prelude::*;
+
+use crate::grid::Heightfield;
+use crate::height::HeightSource;
+use crate::params::TerrainParams;
+
+/// what an adaptive build produced, next to what the full grid would have cost
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdaptiveStats {
+    pub vertices: usize,
+    pub triangles: usize,
+    /// triangles in the uniform grid over the same samples
+    pub full_triangles: usize,
+    /// bound on the vertical gap between the mesh and any dropped sample, meters
+    pub max_error: f32,
+}
+
+/// error-bounded mesh over the params footprint: a right-triangulated irregular
+/// network that only splits a triangle while its hypotenuse midpoint is more
+/// than `max_error` meters off the surface. the grid is `res` rounded up to a
+/// power of two, since RTIN halves triangles down to single cells. like
+/// `build_europa_mesh` it samples `height` as given, curved or not
+pub fn build_europa_mesh_adaptive(
+    p: TerrainParams,
+    height: &dyn HeightSource,
+    max_error: f32,
+) -> (Mesh, AdaptiveStats) {
+    let tile = p.res.max(1).next_power_of_two() as usize;
+    let half = p.size * 0.5;
+    let field = Heightfield::sample(
+        height,
+        Vec2::splat(-half),
+        p.size / tile as f32,
+        tile + 1,
+        tile + 1,
+    );
+    build_adaptive_mesh(&field, max_error)
+}
+
+/// RTIN mesh of a square heightfield with `2^k + 1` samples per side.
+/// positions are in world xz, normals come from the samples by central differences
+pub fn build_adaptive_mesh(field: &Heightfield, max_error: f32) -> (Mesh, AdaptiveStats) {
+    let size = field.nx;
+    assert!(
+        size == field.nz && size >= 2 && (size - 1).is_power_of_two(),
+        "adaptive meshing needs a square field with 2^k + 1 samples per side, got {}x{}",
+        field.nx,
+        field.nz
+    );
+    let errors = rtin_errors(field);
+
+    let mut builder = Builder {
+        field,
+        errors: &errors,
+        max_error: max_error.max(0.0),
+        vertex_of: vec![u32::MAX; size * size],
+        positions: Vec::new(),
+        normals: Vec::new(),
+        uvs: Vec::new(),
+        indices: Vec::new(),
+    };
+    let m = (size - 1) as u32;
+    builder.split([0, 0], [m, m], [m, 0]);
+    builder.split([m, m], [0, 0], [0, m]);
+
+    let dropped_error = (0..size * size)
+        .filter(|&k| builder.vertex_of[k] == u32::MAX)
+        .map(|k| errors[k])
+        .fold(0.0, f32::max);
+    let stats = AdaptiveStats {
+        vertices: builder.positions.len(),
+        triangles: builder.indices.len() / 3,
+        full_triangles: 2 * (size - 1) * (size - 1),
+        max_error: dropped_error,
+    };
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, default());
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, builder.positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, builder.normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, builder.uvs);
+    mesh.insert_indices(Indices::U32(builder.indices));
+    (mesh, stats)
+}
+
+/// per-sample error of leaving that sample out: how far it sits from the
+/// hypotenuse of the triangle it would split, folded up so a parent's error
+/// covers all of its descendants
+fn rtin_errors(field: &Heightfield) -> Vec<f32> {
+    let size = field.nx;
+    let tile = size - 1;
+    let triangles = tile * tile * 2 - 2;
+    let parents = triangles.saturating_sub(tile * tile);
+    let h = |x: usize, y: usize| field.get(x, y);
+
+    let mut errors = vec![0.0_f32; size * size];
+    // leaves first, so children are done before their parents
+    for i in (0..triangles).rev() {
+        let [a, b, c] = triangle_coords(i + 2, tile);
+        let m = [(a[0] + b[0]) / 2, (a[1] + b[1]) / 2];
+        let mid = m[1] * size + m[0];
+        let interpolated = 0.5 * (h(a[0], a[1]) + h(b[0], b[1]));
+        let mut e = (interpolated - h(m[0], m[1])).abs().max(errors[mid]);
+        if i < parents {
+            let left = ((a[1] + c[1]) / 2) * size + (a[0] + c[0]) / 2;
+            let right = ((b[1] + c[1]) / 2) * size + (b[0] + c[0]) / 2;
+            e = e.max(errors[left]).max(errors[right]);
+        }
+        errors[mid] = e;
+    }
+    errors
+}
+
+/// corners (a, b, c) of the triangle with binary id `id`: ids 2 and 3 are the
+/// two halves of the tile, and each further bit picks the left or right child.
+/// a-b is the hypotenuse, c the right angle
+fn triangle_coords(mut id: usize, tile: usize) -> [[usize; 2]; 3] {
+    let (mut a, mut b, mut c) = if id & 1 == 1 {
+        ([0, 0], [tile, tile], [tile, 0])
+    } else {
+        ([tile, tile], [0, 0], [0, tile])
+    };
+    loop {
+        id >>= 1;
+        if id <= 1 {
+            break;
+        }
+        let m = [(a[0] + b[0]) / 2, (a[1] + b[1]) / 2];
+        if id & 1 == 1 {
+            (a, b) = (c, a);
+        } else {
+            (a, b) = (b, c);
+        }
+        c = m;
+    }
+    [a, b, c]
+}
+
+struct Builder<'a> {
+    field: &'a Heightfield,
+    errors: &'a [f32],
+    max_error: f32,
+    /// mesh vertex for each sample, `u32::MAX` while unused
+    vertex_of: Vec<u32>,
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+}
+
+impl Builder<'_> {
+    fn split(&mut self, a: [u32; 2], b: [u32; 2], c: [u32; 2]) {
+        let m = [(a[0] + b[0]) / 2, (a[1] + b[1]) / 2];
+        let leaf = a[0].abs_diff(c[0]) + a[1].abs_diff(c[1]) <= 1;
+        if !leaf && self.errors[m[1] as usize * self.field.nx + m[0] as usize] > self.max_error {
+            self.split(c, a, m);
+            self.split(b, c, m);
+            return;
+        }
+
+        let (ia, ib, ic) = (self.vertex(a), self.vertex(b), self.vertex(c));
+        // keep every triangle facing +Y whichever way the split left it wound
+        let p = |i: u32| Vec3::from(self.positions[i as usize]);
+        let up = (p(ib) - p(ia)).cross(p(ic) - p(ia)).y >= 0.0;
+        if up {
+            self.indices.extend_from_slice(&[ia, ib, ic]);
+        } else {
+            self.indices.extend_from_slice(&[ia, ic, ib]);
+        }
+    }
+
+    fn vertex(&mut self, [i, j]: [u32; 2]) -> u32 {
+        let f = self.field;
+        let (i, j) = (i as usize, j as usize);
+        let k = j * f.nx + i;
+        if self.vertex_of[k] != u32::MAX {
+            return self.vertex_of[k];
+        }
+
+        let x = f.origin.x + i as f32 * f.spacing;
+        let z = f.origin.y + j as f32 * f.spacing;
+        let (il, ir) = (i.saturating_sub(1), (i + 1).min(f.nx - 1));
+        let (jl, jr) = (j.saturating_sub(1), (j + 1).min(f.nz - 1));
+        let dx = (f.get(ir, j) - f.get(il, j)) / ((ir - il) as f32 * f.spacing);
+        let dz = (f.get(i, jr) - f.get(i, jl)) / ((jr - jl) as f32 * f.spacing);
+        let mut normal = Vec3::new(-dx, 1.0, -dz).normalize();
+        if !normal.is_finite() {
+            normal = Vec3::Y;
+        }
+
+        let index = self.positions.len() as u32;
+        self.positions.push([x, f.get(i, j), z]);
+        self.normals.push(normal.to_array());
+        let n = (f.nx - 1) as f32;
+        self.uvs.push([i as f32 / n, j as f32 / n]);
+        self.vertex_of[k] = index;
+        index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(height: impl Fn(f32, f32) -> f32) -> Heightfield {
+        let n = 65;
+        Heightfield {
+            nx: n,
+            nz: n,
+            origin: Vec2::ZERO,
+            spacing: 1.0,
+            data: (0..n * n)
+                .map(|k| height((k % n) as f32, (k / n) as f32))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn flat_ground_collapses_to_two_triangles() {
+        let (_, stats) = build_adaptive_mesh(&field(|x, z| 0.3 * x - 0.1 * z), 0.01);
+        assert_eq!(stats.triangles, 2);
+        assert_eq!(stats.vertices, 4);
+        assert_eq!(stats.full_triangles, 2 * 64 * 64);
+    }
+
+    #[test]
+    fn error_bound_holds_and_zero_error_keeps_every_bump() {
+        let bumpy = field(|x, z| (x * 0.3).sin() * 4.0 + (z * 0.17).cos() * 2.0);
+        let (_, coarse) = build_adaptive_mesh(&bumpy, 0.5);
+        let (_, fine) = build_adaptive_mesh(&bumpy, 0.0);
+        assert!(coarse.max_error <= 0.5);
+        assert!(coarse.triangles < fine.triangles);
+        assert!(fine.triangles <= fine.full_triangles);
+    }
+
+    #[test]
+    fn builds_sample_the_surface_they_are_given() {
+        use crate::height::{HeightFn, arc};
+        use crate::mesh::build_europa_mesh;
+        use bevy::math::DVec2;
+        use bevy::mesh::VertexAttributeValues;
+
+        struct Slope;
+        impl HeightSource for Slope {
+            fn height_at_world(&self, p: DVec2) -> f32 {
+                (0.01 * p.x) as f32
+            }
+        }
+        let positions = |mesh: &Mesh| match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+            Some(VertexAttributeValues::Float32x3(v)) => v.clone(),
+            _ => panic!("no positions"),
+        };
+
+        let p = TerrainParams {
+            size: 4000.0,
+            res: 8,
+            curvature: Some(50_000.0),
+            ..TerrainParams::europa_demo()
+        };
+        let base: HeightFn = arc(Slope);
+        let surface = p.surface(base.clone());
+        // the rendered surface is curved already and must come out as is
+        for mesh in [
+            build_europa_mesh(p, surface.as_ref()),
+            build_europa_mesh_adaptive(p, surface.as_ref(), 0.0).0,
+        ] {
+            let corner = positions(&mesh)
+                .into_iter()
+                .find(|v| v[0] == 2000.0 && v[2] == 2000.0)
+                .expect("far corner vertex");
+            let want = surface.height_at(2000.0, 2000.0);
+            // 20 m of slope and 80 m of sagitta out there, bent once
+            assert!((want + 60.0).abs() < 0.5);
+            assert!((corner[1] - want).abs() < 1e-3, "{} vs {want}", corner[1]);
+        }
+
+        // and the base graph stays flat
+        let flat = build_europa_mesh(p, base.as_ref());
+        for v in positions(&flat) {
+            assert!((v[1] - 0.01 * v[0]).abs() < 1e-4);
+        }
+    }
+}
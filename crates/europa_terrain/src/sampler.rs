@@ -0,0 +1,120 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use crate::HeightResource;
+use crate::height::{HeightFn, HeightSource};
+use crate::origin::{Rebased, WorldOrigin};
+
+/// the surface at one point
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TerrainSample {
+    /// world y, meters
+    pub height: f32,
+    /// unit surface normal
+    pub normal: Vec3,
+    /// angle from horizontal, radians
+    pub slope: f32,
+    /// compass direction the slope faces (downhill), radians clockwise from
+    /// north (-Z) towards east (+X). 0 on flat ground
+    pub aspect: f32,
+}
+
+impl TerrainSample {
+    /// from a height and its xz gradient
+    pub fn from_gradient(height: f32, grad: Vec2) -> Self {
+        let mut normal = Vec3::new(-grad.x, 1.0, -grad.y).normalize();
+        if !normal.is_finite() {
+            normal = Vec3::Y;
+        }
+        let aspect = if grad == Vec2::ZERO {
+            0.0
+        } else {
+            // downhill is -grad; east is +x, north is -z
+            (-grad.x).atan2(grad.y).rem_euclid(std::f32::consts::TAU)
+        };
+        Self {
+            height,
+            normal,
+            slope: grad.length().atan(),
+            aspect,
+        }
+    }
+}
+
+/// read access to the live terrain surface, the same height graph the chunk
+/// meshes are sampled from. follows recipe reloads and param changes.
+/// positions and heights are in render space, relative to `WorldOrigin`
+#[derive(SystemParam)]
+pub struct TerrainSampler<'w> {
+    height: Res<'w, HeightResource>,
+    origin: Res<'w, WorldOrigin>,
+}
+
+impl TerrainSampler<'_> {
+    pub fn height(&self, x: f32, z: f32) -> f32 {
+        let h = self.height.0.height_at_world(self.origin.world_xz(x, z));
+        (h as f64 - self.origin.0.y) as f32
+    }
+
+    pub fn normal(&self, x: f32, z: f32) -> Vec3 {
+        self.sample(x, z).normal
+    }
+
+    /// radians from horizontal
+    pub fn slope(&self, x: f32, z: f32) -> f32 {
+        self.sample(x, z).slope
+    }
+
+    /// radians clockwise from north (-Z), see `TerrainSample::aspect`
+    pub fn aspect(&self, x: f32, z: f32) -> f32 {
+        self.sample(x, z).aspect
+    }
+
+    pub fn sample(&self, x: f32, z: f32) -> TerrainSample {
+        let world = self.origin.world_xz(x, z);
+        let (h, grad) = self.height.0.height_and_gradient_world(world);
+        TerrainSample::from_gradient((h as f64 - self.origin.0.y) as f32, grad)
+    }
+
+    /// the underlying height graph in world coordinates, cheap to clone into tasks
+    pub fn source(&self) -> &HeightFn {
+        &self.height.0
+    }
+
+    /// the surface in render space, as the `x, z` of the methods above
+    pub fn rebased(&self) -> Rebased<HeightFn> {
+        Rebased {
+            source: self.height.0.clone(),
+            origin: self.origin.0,
+        }
+    }
+
+    /// true on the frame the surface was replaced
+    pub fn is_changed(&self) -> bool {
+        self.height.is_changed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+    #[test]
+    fn aspect_points_downhill() {
+        // rising towards +x, so the slope faces west
+        let s = TerrainSample::from_gradient(0.0, Vec2::new(1.0, 0.0));
+        assert!((s.aspect - 3.0 * FRAC_PI_2).abs() < 1e-6);
+        assert!((s.slope - FRAC_PI_4).abs() < 1e-6);
+        // rising towards +z (south), so it faces north
+        let s = TerrainSample::from_gradient(0.0, Vec2::new(0.0, 0.5));
+        assert!(s.aspect.abs() < 1e-6);
+        // rising towards -z, faces south
+        let s = TerrainSample::from_gradient(0.0, Vec2::new(0.0, -0.5));
+        assert!((s.aspect - PI).abs() < 1e-6);
+        assert_eq!(
+            TerrainSample::from_gradient(3.0, Vec2::ZERO).normal,
+            Vec3::Y
+        );
+    }
+}
@@ -1,30 +1,173 @@
+use crate::asset::{RecipeHandle, TerrainRecipe};
+use crate::chunks::{ChunkBuild, ChunkLayout, ChunkSet};
+use crate::height::HeightFn;
+use crate::lod::{ChunkKey, TerrainLod, select_chunks};
+use crate::mesh::{build_patch_mesh, build_patch_mesh_until};
+use crate::origin::WorldOrigin;
+use crate::params::TerrainParams;
+use crate::{BaseHeight, HeightResource, TerrainGenerator};
+use bevy::math::DVec3;
 use bevy::prelude::*;
-use crate::{mesh::build_europa_mesh, params::TerrainParams, HeightResource};
 
-pub(crate) fn spawn_europa(
+pub(crate) type TerrainChunks = ChunkSet<ChunkKey>;
+
+#[derive(Component)]
+pub(crate) struct TerrainChunk;
+
+pub(crate) fn spawn_europa(mut commands: Commands, mut mats: ResMut<Assets<StandardMaterial>>) {
+    let chunks = TerrainChunks::spawn(&mut commands, &mut mats, "Europa Terrain");
+    commands.insert_resource(chunks);
+}
+
+/// the flat patch cut up by the current `TerrainLod`
+struct FlatLayout<'a> {
+    lod: &'a TerrainLod,
+    origin: &'a WorldOrigin,
+    height: &'a HeightFn,
+}
+
+impl ChunkLayout for FlatLayout<'_> {
+    type Key = ChunkKey;
+
+    fn translation(&self, key: &ChunkKey) -> Vec3 {
+        chunk_translation(key, self.lod, self.origin)
+    }
+
+    fn placeholder(&self, key: &ChunkKey) -> Mesh {
+        let lod = self.lod;
+        build_patch_mesh(
+            key.origin(lod),
+            key.size(lod),
+            lod.placeholder_res.clamp(1, lod.chunk_res.max(1)),
+            key.edges(lod),
+            self.height.as_ref(),
+        )
+    }
+
+    fn build(&self, key: &ChunkKey) -> ChunkBuild {
+        let source = self.height.clone();
+        let lod = self.lod;
+        let (origin, size, res, edges) = (
+            key.origin(lod),
+            key.size(lod),
+            lod.chunk_res,
+            key.edges(lod),
+        );
+        Box::new(move |cancelled| {
+            build_patch_mesh_until(origin, size, res, edges, source.as_ref(), cancelled)
+        })
+    }
+
+    fn tag(&self, key: &ChunkKey) -> impl Bundle {
+        (
+            TerrainChunk,
+            Name::new(format!("Terrain Chunk {}/{}/{}", key.depth, key.x, key.z)),
+        )
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn stream_chunks(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
-    mut mats: ResMut<Assets<StandardMaterial>>,
+    mut chunks: ResMut<TerrainChunks>,
+    lod: Res<TerrainLod>,
     params: Res<TerrainParams>,
-    height: Res<HeightResource>
+    height: Res<HeightResource>,
+    origin: Res<WorldOrigin>,
+    cam_q: Query<&Transform, With<Camera3d>>,
 ) {
-    let mesh = build_europa_mesh(*params, height.0.as_ref());
-    let handle = meshes.add(mesh);
-
-    let mat = mats.add(StandardMaterial {
-        // pale, icy
-        base_color: Color::srgb(0.78, 0.83, 0.88),
-        perceptual_roughness: 1.0,
-        reflectance: 0.02,
-        metallic: 0.0,
-        alpha_mode: AlphaMode::Opaque,
-        ..default()
+    if height.is_changed() || lod.is_changed() {
+        chunks.invalidate();
+    }
+    let layout = FlatLayout {
+        lod: &lod,
+        origin: &origin,
+        height: &height.0,
+    };
+    chunks.finish_builds(&mut commands, &mut meshes, &layout);
+
+    let Ok(cam) = cam_q.single() else {
+        return;
+    };
+    let eye = origin.to_world(cam.translation);
+    let curvature = params.curvature;
+    let mut wanted = select_chunks(&lod, eye, curvature);
+    wanted.sort_by(|a, b| {
+        a.distance(&lod, eye, curvature)
+            .total_cmp(&b.distance(&lod, eye, curvature))
     });
+    chunks.update(&mut commands, &mut meshes, &layout, &wanted, lod.budget());
+}
+
+/// render-space position of a chunk's centre
+fn chunk_translation(key: &ChunkKey, lod: &TerrainLod, origin: &WorldOrigin) -> Vec3 {
+    let center = key.center(lod);
+    origin.to_render(DVec3::new(center.x, 0.0, center.y))
+}
 
-    commands.spawn((
-        Mesh3d(handle),
-        MeshMaterial3d(mat),
-        Transform::from_translation(Vec3::ZERO),
-        Name::new("Europa Terrain"),
-    ));
+/// moves the loaded chunks to where they sit relative to a new `WorldOrigin`
+pub(crate) fn follow_origin(
+    chunks: Res<TerrainChunks>,
+    lod: Res<TerrainLod>,
+    origin: Res<WorldOrigin>,
+    mut q: Query<&mut Transform, With<TerrainChunk>>,
+) {
+    chunks.place(|key| chunk_translation(key, &lod, &origin), &mut q);
+}
+
+/// rebuilds the height graph when the recipe file or the params change
+pub(crate) fn apply_recipe(
+    mut commands: Commands,
+    mut events: MessageReader<AssetEvent<TerrainRecipe>>,
+    recipes: Res<Assets<TerrainRecipe>>,
+    handle: Res<RecipeHandle>,
+    params: Res<TerrainParams>,
+) {
+    let mut dirty = params.is_changed() && !params.is_added();
+    for ev in events.read() {
+        if ev.is_loaded_with_dependencies(&handle.0) || ev.is_modified(&handle.0) {
+            dirty = true;
+        }
+    }
+    if !dirty {
+        return;
+    }
+    let Some(recipe) = recipes.get(&handle.0) else {
+        return;
+    };
+
+    match recipe.height.build(&params) {
+        Ok(height) => {
+            info!("Terrain recipe applied (seed {})", params.seed);
+            commands.insert_resource(BaseHeight(height));
+        }
+        Err(e) => warn!("Terrain recipe not applied: {e}"),
+    }
+}
+
+/// reruns the params-driven generator when `TerrainParams` change
+pub(crate) fn regenerate_height(
+    mut commands: Commands,
+    generator: Res<TerrainGenerator>,
+    params: Res<TerrainParams>,
+) {
+    if !params.is_changed() || params.is_added() {
+        return;
+    }
+    info!("Terrain regenerated from params (seed {})", params.seed);
+    commands.insert_resource(BaseHeight((generator.0)(&params)));
+}
+
+/// rebends the surface when the base graph or `TerrainParams::curvature` change
+pub(crate) fn apply_curvature(
+    mut commands: Commands,
+    base: Res<BaseHeight>,
+    params: Res<TerrainParams>,
+) {
+    let changed = base.is_changed() || params.is_changed();
+    if !changed || (base.is_added() && params.is_added()) {
+        return;
+    }
+    commands.insert_resource(HeightResource(params.surface(base.0.clone())));
 }